@@ -1,7 +1,5 @@
 use thiserror::Error;
 
-use crate::auth::AccountInfo;
-
 #[derive(Error, Debug)]
 pub enum AuthError {
     #[error("Missing email or password")]
@@ -22,8 +20,8 @@ pub enum AuthError {
     #[error("Error setting client credentials config: {0}")]
     ClientCredentialsConfigError(#[from] tokio::sync::SetError<String>),
 
-    #[error("Error setting account info config: {0}")]
-    AccountInfoConfigError(#[from] tokio::sync::SetError<AccountInfo>),
+    #[error("Your session has expired, please log in again")]
+    SessionExpired,
 
     #[error("JSON parsing error: {0}")]
     JsonError(#[from] serde_json::Error),
@@ -1,9 +1,11 @@
-use crate::cache::{set_account_info, get_account_info, set_client_token};
-use crate::config::{ConfigError, get_remember_me_value, save_user_login};
+use crate::cache::set_client_token;
+use crate::config::accounts::update_remembered_token;
+use crate::config::ConfigError;
 
 pub mod errors;
 pub mod services;
 pub mod responses;
+pub mod session;
 
 use base64::prelude::*;
 use reqwest::Client;
@@ -75,14 +77,7 @@ pub async fn login_user(email: String, password: String) -> Result<AccountInfo,
     }
 
     let login_response: LoginResponse = response.json().await?;
-    let account_info: AccountInfo = AccountInfo {
-        access_token: login_response.access_token,
-        refresh_token: login_response.refresh_token,
-        account_id: login_response.account_id,
-        display_name: login_response.display_name,
-    };
-
-    set_account_info(account_info.clone()).await?;
+    let account_info: AccountInfo = session::start_session(login_response).await;
     Ok(account_info)
 }
 
@@ -113,6 +108,10 @@ pub async fn login_user_refresh(refresh_token: &String) -> Result<AccountInfo, A
 
     if response.status() != 200 {
         if let Ok(error_response) = response.json::<ErrorResponse>().await {
+            // The saved login itself is no good anymore, so signing in again is the only way back
+            if error_response.is_token_rejected() {
+                return Err(AuthError::SessionExpired);
+            }
             let error_message = error_response
                 .error_message
                 .unwrap_or_else(|| "Authentication failed".to_string());
@@ -122,32 +121,18 @@ pub async fn login_user_refresh(refresh_token: &String) -> Result<AccountInfo, A
     }
 
     let login_response: LoginResponse = response.json().await?;
-    let account_info: AccountInfo = AccountInfo {
-        access_token: login_response.access_token,
-        refresh_token: login_response.refresh_token,
-        account_id: login_response.account_id,
-        display_name: login_response.display_name,
-    };
+    let account_info: AccountInfo = session::start_session(login_response).await;
 
-    set_account_info(account_info.clone()).await?;
-
-    let remember_me = get_remember_me_value().await.map_err(|_| AuthError::UnexpectedError)?;
-    save_user_login(remember_me, account_info.refresh_token.clone()).await.map_err(|_| AuthError::UnexpectedError)?;
+    update_remembered_token(&account_info).await.map_err(|_| AuthError::UnexpectedError)?;
 
     Ok(account_info)
 }
 
 pub async fn generate_exchange() -> Result<String, ConfigError> {
-    let account_info: &AccountInfo = get_account_info().await?;
-
     let client = Client::new();
     let url = format!("{}/account/api/oauth/exchange", Services::ACCOUNT);
 
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let response = session::send_authorized(|| client.get(&url)).await?;
 
     if response.status() != 200 {
         if let Ok(error_response) = response.json::<ErrorResponse>().await {
@@ -161,4 +146,28 @@ pub async fn generate_exchange() -> Result<String, ConfigError> {
 
     let exchange_response: ExchangeResponse = response.json().await?;
     Ok(exchange_response.code)
-}
\ No newline at end of file
+}
+
+/// Ends a session on the account service, so its tokens stop working
+pub async fn revoke_session(access_token: &str) -> Result<(), AuthError> {
+    let client = Client::new();
+    let url = format!("{0}/account/api/oauth/sessions/kill/{1}", Services::ACCOUNT, access_token);
+
+    let response = client
+        .delete(&url)
+        .header("Authorization", format!("Bearer {}", access_token))
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        if let Ok(error_response) = response.json::<ErrorResponse>().await {
+            let error_message = error_response
+                .error_message
+                .unwrap_or_else(|| "Ending the session failed".to_string());
+            return Err(AuthError::AuthenticationFailed(error_message));
+        }
+        return Err(AuthError::UnexpectedError);
+    }
+
+    Ok(())
+}
@@ -24,15 +24,13 @@ pub struct LoginResponse {
     #[serde(rename = "client_service")]
     pub _client_service: String,
     pub account_id: String,
-    #[serde(rename = "expires_in")]
-    pub _expires_in: i32,
-    #[serde(rename = "expires_at")]
-    pub _expires_at: String,
+    /// Seconds the access token is valid for
+    pub expires_in: i32,
+    pub expires_at: String,
     pub refresh_token: String,
-    #[serde(rename = "refresh_expires")]
-    pub _refresh_expires: i32,
-    #[serde(rename = "refresh_expires_at")]
-    pub _refresh_expires_at: String,
+    /// Seconds the refresh token is valid for
+    pub refresh_expires: i32,
+    pub refresh_expires_at: String,
     #[serde(rename = "auth_method")]
     pub _auth_method: String,
     #[serde(rename = "displayName")]
@@ -75,7 +73,7 @@ pub struct ExchangeResponse {
 #[derive(Debug, Deserialize)]
 pub struct ErrorResponse {
     #[serde(rename = "errorCode")]
-    pub _error_code: Option<String>,
+    pub error_code: Option<String>,
     #[serde(rename = "errorMessage")]
     pub error_message: Option<String>,
     #[serde(rename = "messageVars")]
@@ -89,5 +87,16 @@ pub struct ErrorResponse {
     #[serde(rename = "error_description")]
     pub _error_description: Option<String>,
     #[serde(rename = "error")]
-    pub _error: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ErrorResponse {
+    /// Error code the account service gives a refresh token that expired or was revoked
+    const INVALID_REFRESH_TOKEN: &'static str = "errors.com.epicgames.account.auth_token.invalid_refresh_token";
+
+    /// Whether the token that was sent is no longer any good, as opposed to the request failing for another reason
+    pub fn is_token_rejected(&self) -> bool {
+        self.error.as_deref() == Some("invalid_grant")
+            || self.error_code.as_deref() == Some(Self::INVALID_REFRESH_TOKEN)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,256 @@
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::auth::{login_user_refresh, revoke_session, AccountInfo, AuthError, LoginResponse};
+use crate::config::accounts::{
+    forget_account, get_remembered_account, get_remembered_accounts, get_remembered_token, remember_account,
+};
+use crate::config::{fetch_saved_user_login, save_remember_me_value, ConfigError};
+
+/// Event the frontend listens on to drop what it has of the previous account, like its friends list
+pub const ACCOUNT_CHANGED_EVENT: &str = "account-changed";
+
+/// How long before the access token expires it gets refreshed
+const REFRESH_MARGIN: u64 = 5 * 60;
+
+/// How often the background task checks whether the token is due for a refresh
+const REFRESH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The signed in account along with when its tokens stop working
+#[derive(Clone, Debug, Serialize)]
+pub struct Session {
+    pub account: AccountInfo,
+    /// Unix time the access token expires
+    pub expires_at: u64,
+    /// Unix time the refresh token expires, after which the user has to sign in again
+    pub refresh_expires_at: u64,
+}
+
+impl Session {
+    fn needs_refresh(&self) -> bool {
+        now() + REFRESH_MARGIN >= self.expires_at
+    }
+
+    fn can_refresh(&self) -> bool {
+        now() < self.refresh_expires_at
+    }
+}
+
+/// Who is signed in after a logout or a switch, None after a logout
+#[derive(Clone, Debug, Serialize)]
+pub struct AccountChange {
+    pub account_id: Option<String>,
+    pub display_name: Option<String>,
+}
+
+impl AccountChange {
+    pub fn new(account: Option<&AccountInfo>) -> Self {
+        AccountChange {
+            account_id: account.map(|account| account.account_id.clone()),
+            display_name: account.map(|account| account.display_name.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AccountSummary {
+    pub account_id: String,
+    pub display_name: String,
+    pub last_used: u64,
+    pub signed_in: bool,
+}
+
+static SESSION: OnceLock<RwLock<Option<Session>>> = OnceLock::new();
+
+/// Held while refreshing, so requests that find the token expired at the same time only refresh it once
+static REFRESH_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+
+fn session() -> &'static RwLock<Option<Session>> {
+    SESSION.get_or_init(|| RwLock::new(None))
+}
+
+fn refresh_lock() -> &'static Mutex<()> {
+    REFRESH_LOCK.get_or_init(|| Mutex::new(()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Replaces the session with the one a login or refresh returned
+pub async fn start_session(login_response: LoginResponse) -> AccountInfo {
+    let issued_at = now();
+    let account = AccountInfo {
+        access_token: login_response.access_token,
+        refresh_token: login_response.refresh_token,
+        account_id: login_response.account_id,
+        display_name: login_response.display_name,
+    };
+
+    *session().write().await = Some(Session {
+        account: account.clone(),
+        expires_at: issued_at + login_response.expires_in.max(0) as u64,
+        refresh_expires_at: issued_at + login_response.refresh_expires.max(0) as u64,
+    });
+
+    account
+}
+
+/// Forgets the session, so the next request signs in again from the saved login if there is one
+pub async fn end_session() {
+    *session().write().await = None;
+}
+
+pub async fn get_session() -> Option<Session> {
+    session().read().await.clone()
+}
+
+/// Signs in with the saved refresh token when nothing is signed in yet, as on startup
+async fn restore_session() -> Result<Session, AuthError> {
+    let _guard = refresh_lock().lock().await;
+    if let Some(current) = get_session().await {
+        return Ok(current);
+    }
+
+    match fetch_saved_user_login().await {
+        Ok(_) => get_session().await.ok_or(AuthError::MissingAccessTokenCache),
+        Err(ConfigError::AuthError(e)) => Err(e),
+        Err(_) => Err(AuthError::MissingAccessTokenCache),
+    }
+}
+
+/// Refreshes the access token, unless another refresh replaced `stale_token` while this one waited its turn
+async fn refresh_session(stale_token: &str) -> Result<Session, AuthError> {
+    let _guard = refresh_lock().lock().await;
+
+    let current = get_session().await.ok_or(AuthError::MissingAccessTokenCache)?;
+    if current.account.access_token != stale_token {
+        return Ok(current);
+    }
+    if !current.can_refresh() {
+        end_session().await;
+        return Err(AuthError::SessionExpired);
+    }
+
+    login_user_refresh(&current.account.refresh_token).await?;
+    get_session().await.ok_or(AuthError::MissingAccessTokenCache)
+}
+
+/// The signed in session with an access token that is valid for a while yet
+pub async fn current_session() -> Result<Session, AuthError> {
+    let current = match get_session().await {
+        Some(current) => current,
+        None => restore_session().await?,
+    };
+
+    match current.needs_refresh() {
+        true => refresh_session(&current.account.access_token).await,
+        false => Ok(current),
+    }
+}
+
+pub async fn current_account() -> Result<AccountInfo, AuthError> {
+    Ok(current_session().await?.account)
+}
+
+/// Sends a request with a valid bearer token. A 401 means the token was revoked or expired early, so the request is
+/// built and sent once more with a refreshed one.
+pub async fn send_authorized(request: impl Fn() -> RequestBuilder) -> Result<Response, AuthError> {
+    let token = current_session().await?.account.access_token;
+    let response = request().bearer_auth(&token).send().await?;
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return Ok(response);
+    }
+
+    let refreshed = refresh_session(&token).await?.account.access_token;
+    Ok(request().bearer_auth(&refreshed).send().await?)
+}
+
+/// Signs out: the session ends on the account service and in memory, the account's saved login is forgotten and
+/// nothing signs in on startup anymore. Other remembered accounts stay there to switch to. Returns who was signed in.
+pub async fn logout() -> Result<Option<AccountInfo>, ConfigError> {
+    let _guard = refresh_lock().lock().await;
+    save_remember_me_value(false).await?;
+
+    let Some(current) = session().write().await.take() else {
+        return Ok(None);
+    };
+
+    // Signed out here whatever the account service says
+    if let Err(e) = revoke_session(&current.account.access_token).await {
+        eprintln!("Couldn't end the session on the account service: {}", e);
+    }
+    forget_account(&current.account.account_id).await?;
+
+    Ok(Some(current.account))
+}
+
+/// Signs in to a remembered account in place of the current one. A remembered current account stays signed in to
+/// switch back to, any other one has its session ended since nothing could use it anymore.
+pub async fn switch_account(account_id: &str) -> Result<AccountInfo, ConfigError> {
+    let refresh_token = get_remembered_token(account_id).await?;
+    let _guard = refresh_lock().lock().await;
+    let previous = get_session().await;
+
+    let account_info = match login_user_refresh(&refresh_token).await {
+        Ok(account_info) => account_info,
+        // The saved login expired or was revoked, so there is no point remembering it
+        Err(AuthError::SessionExpired) => {
+            forget_account(account_id).await?;
+            return Err(AuthError::SessionExpired.into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    remember_account(&account_info).await?;
+
+    if let Some(previous) = previous.filter(|previous| previous.account.account_id != account_info.account_id) {
+        if get_remembered_account(&previous.account.account_id).await.is_err() {
+            if let Err(e) = revoke_session(&previous.account.access_token).await {
+                eprintln!("Couldn't end the previous session on the account service: {}", e);
+            }
+        }
+    }
+
+    Ok(account_info)
+}
+
+/// Remembered accounts to switch between, the most recently used first
+pub async fn list_accounts() -> Result<Vec<AccountSummary>, ConfigError> {
+    let signed_in = get_session().await.map(|current| current.account.account_id);
+
+    Ok(get_remembered_accounts()
+        .await?
+        .into_iter()
+        .map(|account| AccountSummary {
+            signed_in: signed_in.as_deref() == Some(account.account_id.as_str()),
+            account_id: account.account_id,
+            display_name: account.display_name,
+            last_used: account.last_used,
+        })
+        .collect())
+}
+
+/// Refreshes the access token shortly before it expires, so requests rarely have to wait on a refresh themselves
+pub async fn keep_session_fresh() {
+    loop {
+        tokio::time::sleep(REFRESH_CHECK_INTERVAL).await;
+
+        let Some(current) = get_session().await else {
+            continue;
+        };
+        if !current.needs_refresh() {
+            continue;
+        }
+
+        if let Err(e) = refresh_session(&current.account.access_token).await {
+            eprintln!("Couldn't refresh the session: {}", e);
+        }
+    }
+}
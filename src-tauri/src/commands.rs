@@ -2,13 +2,20 @@ use base64::prelude::BASE64_STANDARD;
 use tokio::sync::mpsc;
 use win_msgbox::Okay;
 
+use crate::auth::session::{self, AccountChange, AccountSummary, ACCOUNT_CHANGED_EVENT};
 use crate::auth::{generate_exchange, login_client, login_user, AccountInfo, AuthError, Services};
 use crate::cache::{get_account_info, get_client_token};
+use crate::config::accounts::forget_account;
 use crate::config::drives::{get_install_locations, InstallLocation};
 use crate::config::installed::{
-    add_or_update_object, get_object_by_artifact_id, update_object_by_artifact_id, InstalledObject,
+    add_or_update_object, get_installed_objects, get_object_by_artifact_id, get_object_by_install_id,
+    get_object_by_location, update_object_by_artifact_id, InstalledObject,
+};
+use crate::config::install_state::{get_install_state, transition, unix_now, InstallState};
+use crate::config::{
+    fetch_saved_user_login, get_hash_policy, get_shared_store_enabled, save_hash_policy, save_shared_store_enabled,
+    save_user_login, ConfigError,
 };
-use crate::config::{fetch_saved_user_login, save_user_login, ConfigError};
 
 use crate::discord::errors::DiscordError;
 use crate::friends::errors::FriendError;
@@ -20,22 +27,39 @@ use crate::game::responses::GameInfo;
 
 use crate::manifest::downloader::downloader::download_game;
 use crate::manifest::downloader::errors::DownloadError;
-use crate::manifest::downloader::{get_second_latest_manifest_b64, mark_game_as_deleted};
+use crate::manifest::downloader::{find_cached_manifest, get_cached_manifests, mark_game_as_deleted};
+use crate::manifest::downloader::rollback::rollback_to_build;
 use crate::manifest::downloader::progress_update::ProgressUpdate;
+use crate::manifest::downloader::relocate;
+use crate::manifest::downloader::storage::{self, StorageReport, MANIFEST_RETENTION};
+use crate::manifest::downloader::store::{clean_store, release_install, share_install};
+use crate::manifest::downloader::preflight::{self, PreflightReport};
+use crate::manifest::downloader::import::{self, ImportReport};
+use crate::manifest::downloader::transaction::has_pending_journal;
+use crate::manifest::downloader::uninstall::{plan_uninstall, UninstallPlan};
+use crate::manifest::downloader::hashing::HashPolicy;
+use crate::manifest::downloader::integrity::VerifyMode;
+use crate::manifest::downloader::orphans::{find_orphan_files, remove_orphan_files, OrphanFile};
+use crate::manifest::downloader::report::{verify_report, VerifyReport};
 use crate::manifest::downloader::verifier::verify_and_repair_parallel;
 use crate::manifest::{fetch_current_manifest_as_b64, mark_current_manifest_as_complete, parse_manifest, ManifestError, ParsedManifest};
 
 use crate::launcher::errors::LaunchError;
 use crate::launcher::game_launcher::{GameLauncher, LaunchConfig, ProcessUtils};
 
+use crate::operations::persist::{dismiss_interrupted, load_interrupted, InterruptedOperation};
+use crate::operations::guard::ensure_can_launch;
+use crate::operations::{GameRunningPolicy, OperationError, OperationInfo, OperationKind, OperationManager};
+
 use base64::Engine;
+use sha1::{Digest, Sha1};
 use std::path::PathBuf;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
     Arc,
 };
 use std::time::Duration;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 use tokio::fs;
 
 use crate::discord::discord_rpc_utils::DiscordRpcUtils;
@@ -44,22 +68,12 @@ use crate::discord::discord_rpc_utils::DiscordRpcUtils;
 pub struct DownloadControl {
     pub cancelled: AtomicBool,
     pub progress: Arc<tokio::sync::Mutex<Vec<ProgressUpdate>>>,
+    /// Chunk bytes a repair has downloaded so far
+    pub fetched_bytes: AtomicU64,
 }
 
-// Add global state to track current operations
-static CURRENT_DOWNLOAD_CONTROL: std::sync::OnceLock<Arc<tokio::sync::RwLock<Option<Arc<DownloadControl>>>>> = std::sync::OnceLock::new();
-static CURRENT_UNINSTALL_CONTROL: std::sync::OnceLock<Arc<tokio::sync::RwLock<Option<Arc<DownloadControl>>>>> = std::sync::OnceLock::new();
-
 static DISCORD_RPC: std::sync::OnceLock<Arc<tokio::sync::RwLock<Option<DiscordRpcUtils>>>> = std::sync::OnceLock::new();
 
-fn get_global_control() -> &'static Arc<tokio::sync::RwLock<Option<Arc<DownloadControl>>>> {
-    CURRENT_DOWNLOAD_CONTROL.get_or_init(|| Arc::new(tokio::sync::RwLock::new(None)))
-}
-
-fn get_global_uninstall_control() -> &'static Arc<tokio::sync::RwLock<Option<Arc<DownloadControl>>>> {
-    CURRENT_UNINSTALL_CONTROL.get_or_init(|| Arc::new(tokio::sync::RwLock::new(None)))
-}
-
 fn get_global_discord_rpc() -> &'static Arc<tokio::sync::RwLock<Option<DiscordRpcUtils>>> {
     DISCORD_RPC.get_or_init(|| Arc::new(tokio::sync::RwLock::new(None)))
 }
@@ -128,10 +142,11 @@ pub async fn loginc() -> Result<String, AuthError> {
     login_client().await
 }
 
-/// Caches user login data
+/// Remembers the signed in account so it signs in on startup, or forgets it
 #[tauri::command]
-pub async fn saveu(enable_remember_me: bool, refresh_token: String) -> Result<(), ConfigError> {
-    save_user_login(enable_remember_me, refresh_token).await
+pub async fn saveu(enable_remember_me: bool) -> Result<(), ConfigError> {
+    let account_info = get_account_info().await?;
+    save_user_login(enable_remember_me, &account_info).await
 }
 
 /// Gets the cached user login data
@@ -142,74 +157,96 @@ pub async fn fetchsu() -> Result<AccountInfo, ConfigError> {
 
 /// Gets or initializes the cached user login data, but in memory as well
 #[tauri::command]
-pub async fn fetchcu() -> Result<&'static AccountInfo, ConfigError> {
+pub async fn fetchcu() -> Result<AccountInfo, ConfigError> {
     get_account_info().await
 }
 
+/// Signs out of the current account and tells the frontend to drop what it has of it
+#[tauri::command]
+pub async fn logout(app: AppHandle) -> Result<(), ConfigError> {
+    session::logout().await?;
+
+    if let Err(e) = app.emit(ACCOUNT_CHANGED_EVENT, AccountChange::new(None)) {
+        eprintln!("Couldn't tell the frontend about the logout: {}", e);
+    }
+    Ok(())
+}
+
+/// Signs in to another remembered account
+#[tauri::command]
+pub async fn switch_account(account_id: String, app: AppHandle) -> Result<AccountInfo, ConfigError> {
+    let account_info = session::switch_account(&account_id).await?;
+
+    if let Err(e) = app.emit(ACCOUNT_CHANGED_EVENT, AccountChange::new(Some(&account_info))) {
+        eprintln!("Couldn't tell the frontend about the account switch: {}", e);
+    }
+    Ok(account_info)
+}
+
+/// Lists the remembered accounts
+#[tauri::command]
+pub async fn list_accounts() -> Result<Vec<AccountSummary>, ConfigError> {
+    session::list_accounts().await
+}
+
+/// Forgets a remembered account, so it needs its password again
+#[tauri::command]
+pub async fn forget_remembered_account(account_id: String) -> Result<(), ConfigError> {
+    forget_account(&account_id).await
+}
+
 /// Gets or initializes the cached client credentials data, but in memory as well
 #[tauri::command]
 pub async fn fetchcc() -> Result<&'static String, AuthError> {
     get_client_token().await
 }
 
-/// Gets the progress of a download/verification/uninstall
+/// Gets the progress of the running download/verification/uninstall
 #[tauri::command]
 pub async fn get_progress(
-    _control: State<'_, Arc<DownloadControl>>, // Keep for compatibility but don't use
+    manager: State<'_, Arc<OperationManager>>,
 ) -> Result<Vec<ProgressUpdate>, DownloadError> {
-    // First check if there's an active download/verification
-    let global_control = get_global_control().read().await;
-    if let Some(ref control) = *global_control {
-        return Ok(control.progress.lock().await.clone());
-    }
-    drop(global_control);
-    
-    // Then check if there's an active uninstall
-    let global_uninstall_control = get_global_uninstall_control().read().await;
-    if let Some(ref control) = *global_uninstall_control {
-        return Ok(control.progress.lock().await.clone());
-    }
-    
-    // No active operations
-    Ok(Vec::new())
+    Ok(manager.current_progress().await)
 }
 
-/// Cancels a download or verification
+/// Cancels a download or verification. A cancelled update leaves the previous build in place,
+/// a cancelled install only loses the files it had downloaded so far.
 #[tauri::command]
-pub async fn cancel_download(install_dir: String, _control: State<'_, Arc<DownloadControl>>) -> Result<(), ManifestError> {
-    // Cancel the current download
-    let global_control = get_global_control().read().await;
-    if let Some(ref control) = *global_control {
-        control.cancelled.store(true, Ordering::Relaxed);
+pub async fn cancel_download(install_dir: String, manager: State<'_, Arc<OperationManager>>) -> Result<(), ManifestError> {
+    // Cancel every operation running or queued on this install
+    let cancelled = manager.cancel_install_dir(&install_dir);
+    if !cancelled.is_empty() {
         eprintln!("Download cancellation requested");
     }
-    drop(global_control);
-    
-    // Wait a bit for the download to actually cancel
-    tokio::time::sleep(Duration::from_millis(500)).await;
-    
-    // Now clean up with a separate control for uninstall
-    let uninstall_control = Arc::new(DownloadControl::default());
-    start_uninstall_internal(Some(install_dir), uninstall_control).await.map_err(|_| ManifestError::UnexpectedError)?;
-    uninstall_complete().await
+
+    // The cancelled operations discard their own staged files, so returning once they finished means cleanup is done
+    manager.wait_for(&cancelled).await;
+    Ok(())
 }
 
 /// Cancels an uninstall operation
 #[tauri::command]
-pub async fn cancel_uninstall(_control: State<'_, Arc<DownloadControl>>) -> Result<(), DownloadError> {
-    let global_uninstall_control = get_global_uninstall_control().read().await;
-    if let Some(ref control) = *global_uninstall_control {
-        control.cancelled.store(true, Ordering::Relaxed);
-        eprintln!("Uninstall cancellation requested");
-        Ok(())
-    } else {
-        Err(DownloadError::UnexpectedError)
+pub async fn cancel_uninstall(manager: State<'_, Arc<OperationManager>>) -> Result<(), DownloadError> {
+    let uninstalls: Vec<OperationInfo> = manager
+        .list()
+        .into_iter()
+        .filter(|info| info.kind == OperationKind::Uninstall)
+        .collect();
+
+    if uninstalls.is_empty() {
+        return Err(DownloadError::UnexpectedError);
     }
+
+    for info in uninstalls {
+        let _ = manager.cancel(info.id);
+    }
+    eprintln!("Uninstall cancellation requested");
+    Ok(())
 }
 
 /// Resumes a download or verification
 #[tauri::command]
-pub fn resume_download(_control: State<'_, Arc<DownloadControl>>) {
+pub fn resume_download(_manager: State<'_, Arc<OperationManager>>) {
     // This doesn't really make sense with the current architecture
     eprintln!("Resume download called - this operation is not supported");
 }
@@ -222,10 +259,6 @@ pub async fn start_download_internal(
     install_dir: String,
     control: Arc<DownloadControl>,
 ) -> Result<(), DownloadError> {
-    // Set this as the current download control
-    *get_global_control().write().await = Some(control.clone());
-    
-    control.cancelled.store(false, Ordering::Relaxed);
     control.progress.lock().await.clear();
 
     let path = PathBuf::from(&install_dir);
@@ -251,7 +284,7 @@ pub async fn start_download_internal(
         None => None,
     };
 
-    let result = download_game(
+    download_game(
         parsed_manifest,
         bucket,
         path,
@@ -259,44 +292,181 @@ pub async fn start_download_internal(
         control.clone(),
         old_parsed_manifest,
     )
-    .await;
-    
-    // Clear the global control when done
-    *get_global_control().write().await = None;
-    
-    result
+    .await
+}
+
+/// Moves the install to a new state, returning the one it was in. A change that isn't allowed is logged rather than failing the operation around it.
+async fn set_install_state(install_dir: &str, state: InstallState) -> Option<InstallState> {
+    match transition(install_dir, state, |_| {}).await {
+        Ok(previous) => Some(previous),
+        Err(e) => {
+            eprintln!("Couldn't change the install state: {}", e);
+            None
+        }
+    }
+}
+
+/// Every file gets installed, so the selected tags are all the tags the build uses
+fn manifest_install_tags(manifest: &ParsedManifest) -> Vec<String> {
+    let mut tags: Vec<String> = manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .flat_map(|fm| fm.install_tags.iter().cloned())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
 }
 
-/// Download a game
+/// The install picked by id, or the first install of the game when none is picked
+async fn resolve_install(install_id: Option<String>) -> Result<InstalledObject, ConfigError> {
+    match install_id {
+        Some(install_id) => get_object_by_install_id(&install_id).await,
+        None => get_object_by_artifact_id(Services::CATALOG_ID).await,
+    }
+}
+
+/// The cached manifest of a build, base64 encoded
+async fn cached_manifest_b64(build_version: &str) -> Option<String> {
+    let (path, _) = find_cached_manifest(build_version).await.ok()?;
+    fs::read(path).await.ok().map(|data| BASE64_STANDARD.encode(data))
+}
+
+/// The manifest of the build an install has, so each install is checked against its own build rather than the live one
+async fn install_manifest_b64(installed: &InstalledObject) -> Result<String, DownloadError> {
+    match cached_manifest_b64(&installed.app_version).await {
+        Some(manifest_b64) => Ok(manifest_b64),
+        None => Ok(fetch_current_manifest_as_b64().await?),
+    }
+}
+
+/// The manifest of the build to download: a chosen one out of the manifests cached for the installs, or the live one
+async fn target_manifest_b64(build_version: Option<&str>) -> Result<String, DownloadError> {
+    match build_version {
+        Some(build_version) => cached_manifest_b64(build_version)
+            .await
+            .ok_or_else(|| ManifestError::BuildNotCached(build_version.to_string()).into()),
+        None => Ok(fetch_current_manifest_as_b64().await?),
+    }
+}
+
+/// Download a game, the live build unless another build whose manifest is cached is chosen
 #[tauri::command]
 pub async fn start_download(
-    install_dir: String
+    install_dir: String,
+    build_version: Option<String>,
+    if_game_running: Option<GameRunningPolicy>,
+    manager: State<'_, Arc<OperationManager>>,
 ) -> Result<(), DownloadError> {
-    // Create a new control specifically for this download
-    let download_control = Arc::new(DownloadControl::default());
-    
-    match get_second_latest_manifest_b64().await {
-        Ok(old_manifest_b64) => {
+    let kind = match get_install_state(&install_dir).await.is_installed() {
+        true => OperationKind::Update,
+        false => OperationKind::Install,
+    };
+
+    // Chunks are copied from the build already in the folder, and from the other installs, instead of downloaded
+    let installed_version = get_object_by_location(&install_dir)
+        .await
+        .map(|installed| installed.app_version)
+        .unwrap_or_default();
+
+    // A fresh install into a folder it had to create shouldn't leave that folder behind when cancelled
+    let install_path = PathBuf::from(&install_dir);
+    let created_dir = kind == OperationKind::Install && !install_path.exists();
+
+    let result = manager.run_guarded(kind, install_dir.clone(), if_game_running.unwrap_or_default(), |download_control| async move {
+        let manifest_b64 = target_manifest_b64(build_version.as_deref()).await?;
+
+        // Refuse a folder the build can't go into before anything is written to it
+        let mut buf = Vec::<u8>::new();
+        BASE64_STANDARD.decode_vec(&manifest_b64, &mut buf)?;
+        let manifest_hash = hex::encode(Sha1::digest(&buf));
+        let parsed_manifest = parse_manifest(buf).await?;
+        preflight::ensure_install_location(&PathBuf::from(&install_dir), &parsed_manifest).await?;
+
+        let install_tags = manifest_install_tags(&parsed_manifest);
+        let app_name = parsed_manifest.meta.app_name.clone();
+        drop(parsed_manifest);
+
+        let previous = set_install_state(&install_dir, match kind {
+            OperationKind::Install => InstallState::Installing,
+            _ => InstallState::Updating,
+        })
+        .await;
+
+        let result = async {
             start_download_internal(
-                fetch_current_manifest_as_b64().await?,
-                Some(old_manifest_b64),
+                manifest_b64,
+                cached_manifest_b64(&installed_version).await,
                 "reality-manifest".to_string(),
-                install_dir,
+                install_dir.clone(),
                 download_control,
             )
-            .await
+            .await?;
+
+            // The new build is only recorded once every file has been committed. The live build's details come from
+            // the catalog, a chosen build is recorded from its own manifest along with the state below.
+            if build_version.is_none() {
+                mark_current_manifest_as_complete(&install_dir).await?;
+            }
+            Ok(())
         }
-        Err(_) => {
-            start_download_internal(
-                fetch_current_manifest_as_b64().await?,
-                None,
-                "reality-manifest".to_string(),
-                install_dir,
-                download_control,
-            )
-            .await
+        .await;
+
+        match &result {
+            Ok(()) => {
+                // A chosen build is behind the live one, so it shows up as having an update the next time that's checked
+                let recorded = transition(&install_dir, InstallState::Installed, |installed| {
+                    if let Some(build_version) = build_version {
+                        installed.app_version = build_version;
+                        if installed.namespace_id.is_empty() {
+                            installed.namespace_id = app_name.clone();
+                        }
+                        installed.app_name = app_name;
+                        installed.rolled_back_from = None;
+                    }
+                    installed.manifest_hash = Some(manifest_hash);
+                    installed.install_tags = install_tags;
+                })
+                .await;
+                if let Err(e) = recorded {
+                    eprintln!("Couldn't change the install state: {}", e);
+                }
+                share_install(&install_dir).await;
+            }
+            // A journal left behind means the commit failed and couldn't be rolled back, so files are half replaced
+            Err(_) if has_pending_journal(&PathBuf::from(&install_dir)) => {
+                set_install_state(&install_dir, InstallState::NeedsRepair).await;
+            }
+            // Nothing is committed until every file is ready, so a failed or cancelled install leaves nothing and a
+            // failed or cancelled update leaves the previous build as it was
+            Err(_) if kind == OperationKind::Install => {
+                set_install_state(&install_dir, InstallState::NotInstalled).await;
+            }
+            Err(_) => {
+                if let Some(previous) = previous {
+                    set_install_state(&install_dir, previous).await;
+                }
+            }
         }
+
+        result
+    })
+    .await;
+
+    if created_dir && matches!(result, Err(DownloadError::Cancelled)) {
+        // Only succeeds if the folder is empty, so nothing that was put there in the meantime is lost
+        let _ = fs::remove_dir(&install_path).await;
     }
+
+    result
+}
+
+/// Checks whether a folder can take the current build: free space, writability, path length and what is already in it
+#[tauri::command]
+pub async fn preflight_install_location(path: String) -> Result<PreflightReport, DownloadError> {
+    let manifest = fetch_current_parsed_manifest().await?;
+    Ok(preflight::preflight_install_location(&PathBuf::from(path), &manifest).await)
 }
 
 /// Saves the downloaded data to the disk and marks the download as complete
@@ -305,6 +475,11 @@ pub async fn start_download(
 pub async fn download_complete(
     install_dir: String
 ) -> Result<(), ManifestError> {
+    // Never record a build whose update is still being applied
+    if has_pending_journal(&PathBuf::from(&install_dir)) {
+        return Err(ManifestError::UpdateNotCommitted);
+    }
+
     mark_current_manifest_as_complete(&install_dir).await
 }
 
@@ -314,10 +489,10 @@ pub fn message_box_okay(message: String) {
     let _ = win_msgbox::show::<Okay>(message.as_str());
 }
 
-/// Marks the game as uninstalled by removing everything
+/// Marks an install as uninstalled by removing its record, and the cached manifests once no install is left
 #[tauri::command]
-pub async fn uninstall_complete() -> Result<(), ManifestError> {
-    mark_game_as_deleted().await
+pub async fn uninstall_complete(install_id: Option<String>) -> Result<(), ManifestError> {
+    mark_game_as_deleted(install_id.as_deref()).await
 }
 
 /// This handles the game verification internally with a control to return progress updates
@@ -326,11 +501,8 @@ pub async fn start_verify_internal(
     bucket: String,
     install_dir: String,
     control: Arc<DownloadControl>,
+    mode: VerifyMode,
 ) -> Result<(), DownloadError> {
-    // Set this as the current operation control
-    *get_global_control().write().await = Some(control.clone());
-    
-    control.cancelled.store(false, Ordering::Relaxed);
     control.progress.lock().await.clear();
 
     let path = PathBuf::from(&install_dir);
@@ -347,40 +519,144 @@ pub async fn start_verify_internal(
     BASE64_STANDARD.decode_vec(manifest_b64, &mut buf)?;
     let parsed_manifest: ParsedManifest = parse_manifest(buf).await?;
 
-    let result = verify_and_repair_parallel(parsed_manifest, bucket, path, tx, control).await;
-    
-    // Clear the global control when done
-    *get_global_control().write().await = None;
-    
-    result
+    verify_and_repair_parallel(parsed_manifest, bucket, path, tx, control, mode).await
 }
 
-/// Verify the game files
+/// Verify the files of an install against its build. Quick mode, the default, only re-hashes files that changed since they were last verified.
 #[tauri::command]
-pub async fn start_verify() -> Result<(), DownloadError> {
-    // Create a new control specifically for this verification
-    let verify_control = Arc::new(DownloadControl::default());
-    
-    start_verify_internal(
-        fetch_current_manifest_as_b64().await?, 
-        "reality-manifest".to_string(), 
-        get_object_by_artifact_id(Services::CATALOG_ID).await.map_err(|_| DownloadError::UnexpectedError)?.installation_location, 
-        verify_control
-    ).await
+pub async fn start_verify(
+    install_id: Option<String>,
+    mode: Option<VerifyMode>,
+    if_game_running: Option<GameRunningPolicy>,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<(), DownloadError> {
+    let installed = resolve_install(install_id).await.map_err(|_| DownloadError::UnexpectedError)?;
+    let install_dir = installed.installation_location.clone();
+
+    manager.run_guarded(OperationKind::Verify, install_dir.clone(), if_game_running.unwrap_or_default(), |verify_control| async move {
+        // Fetched before the state changes, so not getting it leaves the install as it was
+        let manifest_b64 = install_manifest_b64(&installed).await?;
+        let previous = set_install_state(&install_dir, InstallState::Verifying).await;
+
+        let result = start_verify_internal(
+            manifest_b64, 
+            "reality-manifest".to_string(), 
+            install_dir.clone(), 
+            verify_control,
+            mode.unwrap_or_default()
+        ).await;
+
+        match &result {
+            Ok(()) => {
+                let verified = transition(&install_dir, InstallState::Installed, |installed| {
+                    installed.last_verified = Some(unix_now());
+                })
+                .await;
+                if let Err(e) = verified {
+                    eprintln!("Couldn't change the install state: {}", e);
+                }
+                // A repair may have given files copies of their own
+                share_install(&install_dir).await;
+            }
+            // Every file that failed was damaged and couldn't be repaired
+            Err(DownloadError::Multiple(_)) => {
+                set_install_state(&install_dir, InstallState::NeedsRepair).await;
+            }
+            // Cancelled, or failed before any file was checked
+            Err(_) => {
+                if let Some(previous) = previous {
+                    set_install_state(&install_dir, previous).await;
+                }
+            }
+        }
+
+        result
+    })
+    .await
 }
 
-/// Internal uninstall function that doesn't interfere with download cancellation
-async fn start_uninstall_internal(
-    install_dir: Option<String>,
+/// This handles report-only verification internally with a control to return progress updates
+pub async fn verify_report_internal(
+    manifest_b64: String,
+    install_dir: String,
+    control: Arc<DownloadControl>,
+    mode: VerifyMode,
+) -> Result<VerifyReport, DownloadError> {
+    control.progress.lock().await.clear();
+
+    let path = PathBuf::from(&install_dir);
+    let (tx, mut rx) = mpsc::channel(128);
+
+    let progress_handle = control.progress.clone();
+    tokio::spawn(async move {
+        while let Some(progress) = rx.recv().await {
+            progress_handle.lock().await.push(progress);
+        }
+    });
+
+    let mut buf = Vec::<u8>::new();
+    BASE64_STANDARD.decode_vec(manifest_b64, &mut buf)?;
+    let parsed_manifest: ParsedManifest = parse_manifest(buf).await?;
+
+    verify_report(&parsed_manifest, path, mode, tx, control).await
+}
+
+/// Checks the files of an install and reports what is wrong without repairing anything
+#[tauri::command]
+pub async fn get_verify_report(
+    install_id: Option<String>,
+    mode: Option<VerifyMode>,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<VerifyReport, DownloadError> {
+    let installed = resolve_install(install_id).await.map_err(|_| DownloadError::UnexpectedError)?;
+    let install_dir = installed.installation_location.clone();
+
+    manager.run(OperationKind::Verify, install_dir.clone(), |verify_control| async move {
+        let manifest_b64 = install_manifest_b64(&installed).await?;
+        let previous = set_install_state(&install_dir, InstallState::Verifying).await;
+
+        let result = verify_report_internal(
+            manifest_b64,
+            install_dir.clone(),
+            verify_control,
+            mode.unwrap_or_default()
+        ).await;
+
+        match (&result, previous) {
+            (Ok(report), _) if !report.is_healthy() => {
+                set_install_state(&install_dir, InstallState::NeedsRepair).await;
+            }
+            (Ok(_), Some(previous)) => {
+                let verified = transition(&install_dir, previous, |installed| {
+                    installed.last_verified = Some(unix_now());
+                })
+                .await;
+                if let Err(e) = verified {
+                    eprintln!("Couldn't change the install state: {}", e);
+                }
+            }
+            (Err(_), Some(previous)) => {
+                set_install_state(&install_dir, previous).await;
+            }
+            _ => {}
+        }
+
+        result
+    })
+    .await
+}
+
+/// This handles rolling back to a cached build internally with a control to return progress updates
+pub async fn rollback_to_version_internal(
+    build_version: String,
+    bucket: String,
+    install_dir: String,
     control: Arc<DownloadControl>,
 ) -> Result<(), DownloadError> {
-    // Set this as the current uninstall control
-    *get_global_uninstall_control().write().await = Some(control.clone());
-    
-    control.cancelled.store(false, Ordering::Relaxed);
     control.progress.lock().await.clear();
 
-    let (tx, mut rx) = tokio::sync::mpsc::channel(128);
+    let path = PathBuf::from(&install_dir);
+    let (tx, mut rx) = mpsc::channel(128);
 
     let progress_handle = control.progress.clone();
     tokio::spawn(async move {
@@ -389,28 +665,220 @@ async fn start_uninstall_internal(
         }
     });
 
-    let path = match install_dir.is_some() {
-        true => install_dir.unwrap(),
-        false => {
-            get_object_by_artifact_id(Services::CATALOG_ID)
+    rollback_to_build(build_version, bucket, path, tx, control).await
+}
+
+/// Rolls an install back to a previous build using its cached manifest
+#[tauri::command]
+pub async fn rollback_to_version(
+    build_version: String,
+    install_id: Option<String>,
+    if_game_running: Option<GameRunningPolicy>,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<(), DownloadError> {
+    let install_dir = resolve_install(install_id).await.map_err(|_| DownloadError::UnexpectedError)?.installation_location;
+
+    manager
+        .run_guarded(OperationKind::Update, install_dir.clone(), if_game_running.unwrap_or_default(), |control| async move {
+            let previous = set_install_state(&install_dir, InstallState::Updating).await;
+
+            let result = rollback_to_version_internal(build_version, "reality-manifest".to_string(), install_dir.clone(), control).await;
+
+            // An older build is installed now, so the live one is an update again
+            match (&result, previous) {
+                (Ok(()), _) => {
+                    share_install(&install_dir).await;
+                    set_install_state(&install_dir, InstallState::UpdateAvailable).await
+                }
+                (Err(_), Some(previous)) => set_install_state(&install_dir, previous).await,
+                _ => None,
+            };
+
+            result
+        })
+        .await
+}
+
+/// Lists the build versions that have a cached manifest and can be rolled back to
+#[tauri::command]
+pub async fn get_cached_build_versions() -> Result<Vec<String>, ManifestError> {
+    let manifests = get_cached_manifests().await?;
+    Ok(manifests
+        .into_iter()
+        .map(|(_, manifest)| manifest.meta.build_version)
+        .collect())
+}
+
+/// Returns whether downloads and verifies check only the strongest file hash or all of them
+#[tauri::command]
+pub fn get_verification_hash_policy() -> HashPolicy {
+    get_hash_policy()
+}
+
+/// Sets whether downloads and verifies check only the strongest file hash or all of them
+#[tauri::command]
+pub async fn set_verification_hash_policy(policy: HashPolicy) -> Result<(), ConfigError> {
+    save_hash_policy(policy).await
+}
+
+/// Returns whether identical files of different installs share one copy in the store
+#[tauri::command]
+pub fn get_shared_store() -> bool {
+    get_shared_store_enabled()
+}
+
+/// Switches the shared store on or off. Switching it on links the files of every settled install right away,
+/// switching it off leaves existing links alone, since each of them is a complete file.
+#[tauri::command]
+pub async fn set_shared_store(
+    enabled: bool,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<(), DownloadError> {
+    save_shared_store_enabled(enabled)
+        .await
+        .map_err(|e| DownloadError::Io(e.to_string()))?;
+    if !enabled {
+        return Ok(());
+    }
+
+    let settled = get_installed_objects()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|installed| matches!(installed.state, InstallState::Installed | InstallState::UpdateAvailable));
+    for installed in settled {
+        let install_dir = installed.installation_location;
+        manager
+            .run(OperationKind::Repair, install_dir.clone(), |_control| async move {
+                share_install(&install_dir).await;
+                Ok(())
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Parses the manifest of the live build
+async fn fetch_current_parsed_manifest() -> Result<ParsedManifest, DownloadError> {
+    let mut buf = Vec::<u8>::new();
+    BASE64_STANDARD.decode_vec(fetch_current_manifest_as_b64().await?, &mut buf)?;
+    Ok(parse_manifest(buf).await?)
+}
+
+/// Parses the manifest of the build an install has
+async fn fetch_install_parsed_manifest(installed: &InstalledObject) -> Result<ParsedManifest, DownloadError> {
+    let mut buf = Vec::<u8>::new();
+    BASE64_STANDARD.decode_vec(install_manifest_b64(installed).await?, &mut buf)?;
+    Ok(parse_manifest(buf).await?)
+}
+
+/// Lists files in the install directory that the installed build doesn't use
+#[tauri::command]
+pub async fn get_orphan_files(install_id: Option<String>) -> Result<Vec<OrphanFile>, DownloadError> {
+    let installed = resolve_install(install_id).await.map_err(|_| DownloadError::UnexpectedError)?;
+    let manifest = fetch_install_parsed_manifest(&installed).await?;
+
+    Ok(find_orphan_files(&PathBuf::from(installed.installation_location), &manifest)?)
+}
+
+/// Deletes orphaned files, or only the selected ones. Returns how many bytes were freed.
+#[tauri::command]
+pub async fn delete_orphan_files(
+    files: Option<Vec<String>>,
+    install_id: Option<String>,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<u64, DownloadError> {
+    let installed = resolve_install(install_id).await.map_err(|_| DownloadError::UnexpectedError)?;
+    let install_dir = installed.installation_location.clone();
+
+    manager.run(OperationKind::Repair, install_dir.clone(), |_control| async move {
+        let manifest = fetch_install_parsed_manifest(&installed).await?;
+        remove_orphan_files(&PathBuf::from(install_dir), &manifest, files).await
+    })
+    .await
+}
+
+/// Reports how much space each install takes, by folder and install tag, along with the manifest cache and the shared store
+#[tauri::command]
+pub async fn get_storage_report() -> Result<StorageReport, DownloadError> {
+    storage::storage_report().await
+}
+
+/// Deletes cached manifests no install is on beyond the newest few kept for rollbacks. Returns how many bytes were freed.
+#[tauri::command]
+pub async fn clean_manifest_cache(keep: Option<usize>) -> Result<u64, ManifestError> {
+    storage::prune_manifest_cache(keep.unwrap_or(MANIFEST_RETENTION)).await
+}
+
+/// Deletes temp files that unfinished operations left in an install, or in every install. Returns how many bytes were freed.
+#[tauri::command]
+pub async fn clean_temp_files(
+    install_id: Option<String>,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<u64, DownloadError> {
+    let installs = match install_id {
+        Some(install_id) => vec![get_object_by_install_id(&install_id)
             .await
-            .map_err(|_| DownloadError::UnexpectedError)?
-            .installation_location
-        }
+            .map_err(|_| DownloadError::UnexpectedError)?],
+        None => get_installed_objects().await.unwrap_or_default(),
     };
-    
+
+    let mut freed = 0u64;
+    for installed in installs {
+        // Running under the manager keeps this away from the temp files of a download that is still going
+        let install_dir = installed.installation_location.clone();
+        freed += manager
+            .run(OperationKind::Repair, install_dir.clone(), |_control| async move {
+                let manifest = find_cached_manifest(&installed.app_version)
+                    .await
+                    .ok()
+                    .map(|(_, manifest)| manifest);
+                storage::remove_stale_temp_files(&PathBuf::from(install_dir), manifest.as_ref()).await
+            })
+            .await?;
+    }
+
+    Ok(freed)
+}
+
+/// Removes stored files no install links to anymore. Returns how many bytes were freed.
+#[tauri::command]
+pub async fn clean_shared_store() -> Result<u64, DownloadError> {
+    clean_store().await
+}
+
+/// Internal uninstall function that doesn't interfere with download cancellation. Sets `deleted_any` once a file is
+/// gone, so a failure can tell whether the install was touched.
+async fn start_uninstall_internal(
+    path: String,
+    control: Arc<DownloadControl>,
+    deleted_any: &mut bool,
+) -> Result<(), DownloadError> {
+    control.progress.lock().await.clear();
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(128);
+
+    let progress_handle = control.progress.clone();
+    tokio::spawn(async move {
+        while let Some(progress) = rx.recv().await {
+            progress_handle.lock().await.push(progress);
+        }
+    });
+
     let install_path = PathBuf::from(&path);
     
-    // Collect all files first to get total count and sizes
-    let mut files_to_delete = Vec::new();
-    let mut total_size = 0u64;
-    
-    if install_path.exists() {
-        collect_files_recursive(&install_path, &mut files_to_delete, &mut total_size).await?;
-    }
+    // Only files of the game and the launcher are deleted, never whatever else shares the folder
+    let plan = plan_uninstall(&install_path).await?;
+
+    let files_to_delete: Vec<(PathBuf, u64)> = plan
+        .files
+        .iter()
+        .map(|file| (install_path.join(&file.filename), file.size))
+        .collect();
     
     let total_files = files_to_delete.len();
-    let total_bytes = total_size;
+    let total_bytes = plan.total_bytes;
     let mut deleted_bytes = 0u64;
     
     // If no files to delete, send completion immediately
@@ -431,7 +899,6 @@ async fn start_uninstall_internal(
         
         // Give time for progress update to be processed
         tokio::time::sleep(Duration::from_millis(200)).await;
-        *get_global_uninstall_control().write().await = None;
         return Ok(());
     }
 
@@ -439,7 +906,6 @@ async fn start_uninstall_internal(
     for (file_index, (file_path, file_size)) in files_to_delete.iter().enumerate() {
         // Check for cancellation
         if control.cancelled.load(Ordering::Relaxed) {
-            *get_global_uninstall_control().write().await = None;
             return Err(DownloadError::Cancelled);
         }
 
@@ -452,6 +918,7 @@ async fn start_uninstall_internal(
         // Delete the file
         match fs::remove_file(file_path).await {
             Ok(()) => {
+                *deleted_any = true;
                 deleted_bytes += file_size;
             }
             Err(e) => {
@@ -494,43 +961,151 @@ async fn start_uninstall_internal(
     // Give time for the final progress update to be processed
     tokio::time::sleep(Duration::from_millis(200)).await;
     
-    // Clear the global uninstall control when done
-    *get_global_uninstall_control().write().await = None;
-    
     Ok(())
 }
 
-/// Uninstall the game by deleting all files
+/// Lists exactly which files uninstalling an install would delete and which it would keep
 #[tauri::command]
-pub async fn start_uninstall(install_dir: Option<String>) -> Result<(), DownloadError> {
-    // Create a separate control for uninstall so it doesn't interfere with other operations
-    let uninstall_control = Arc::new(DownloadControl::default());
-    start_uninstall_internal(install_dir, uninstall_control).await
+pub async fn preview_uninstall(install_id: Option<String>) -> Result<UninstallPlan, DownloadError> {
+    let path = resolve_install(install_id).await.map_err(|_| DownloadError::UnexpectedError)?.installation_location;
+
+    plan_uninstall(&PathBuf::from(path)).await
 }
 
-fn collect_files_recursive<'a>(
-    dir: &'a PathBuf,
-    files: &'a mut Vec<(PathBuf, u64)>,
-    total_size: &'a mut u64,
-) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), DownloadError>> + Send + 'a>> {
-    Box::pin(async move {
-        let mut entries = fs::read_dir(dir).await?;
-        
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            let metadata = entry.metadata().await?;
-            
-            if metadata.is_file() {
-                let size = metadata.len();
-                *total_size += size;
-                files.push((path, size));
-            } else if metadata.is_dir() {
-                collect_files_recursive(&path, files, total_size).await?;
+/// Uninstall an install by deleting its files
+#[tauri::command]
+pub async fn start_uninstall(
+    install_id: Option<String>,
+    if_game_running: Option<GameRunningPolicy>,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<(), DownloadError> {
+    let operation_dir = resolve_install(install_id).await.map_err(|_| DownloadError::UnexpectedError)?.installation_location;
+
+    manager
+        .run_guarded(OperationKind::Uninstall, operation_dir.clone(), if_game_running.unwrap_or_default(), |uninstall_control| async move {
+            let previous = set_install_state(&operation_dir, InstallState::Uninstalling).await;
+
+            let mut deleted_any = false;
+            let result = start_uninstall_internal(operation_dir.clone(), uninstall_control, &mut deleted_any).await;
+
+            match &result {
+                Ok(()) => {
+                    // Stored files only this install linked to go along with it, now that its files are gone
+                    match release_install(&PathBuf::from(&operation_dir)).await {
+                        Ok(freed) if freed > 0 => println!("Freed {} bytes in the shared store", freed),
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Couldn't release the install from the shared store: {}", e),
+                    }
+                    set_install_state(&operation_dir, InstallState::NotInstalled).await;
+                }
+                // Stopping partway leaves only some of the files behind
+                Err(_) if deleted_any => {
+                    set_install_state(&operation_dir, InstallState::NeedsRepair).await;
+                }
+                // Refused or stopped before any file was deleted, so the install is as it was
+                Err(_) => {
+                    if let Some(previous) = previous {
+                        set_install_state(&operation_dir, previous).await;
+                    }
+                }
+            }
+
+            result
+        })
+        .await
+}
+
+/// Adopts a build already on disk, such as one copied from another PC, repairing whatever differs and registering it
+#[tauri::command]
+pub async fn import_install(
+    path: String,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<ImportReport, DownloadError> {
+    manager.run_guarded(OperationKind::Repair, path.clone(), GameRunningPolicy::Refuse, |control| async move {
+        control.progress.lock().await.clear();
+
+        let (tx, mut rx) = mpsc::channel(128);
+
+        let progress_handle = control.progress.clone();
+        tokio::spawn(async move {
+            while let Some(progress) = rx.recv().await {
+                progress_handle.lock().await.push(progress);
+            }
+        });
+
+        // Offline, only the cached builds can be matched
+        let current = match fetch_current_manifest_as_b64().await {
+            Ok(manifest_b64) => {
+                let mut buf = Vec::<u8>::new();
+                BASE64_STANDARD.decode_vec(manifest_b64, &mut buf)?;
+                Some(parse_manifest(buf).await?)
             }
+            Err(e) => {
+                eprintln!("Couldn't fetch the current manifest, matching cached builds only: {}", e);
+                None
+            }
+        };
+
+        let importing = match get_install_state(&path).await.is_installed() {
+            true => InstallState::Verifying,
+            false => InstallState::Installing,
+        };
+        let previous = set_install_state(&path, importing).await;
+
+        let result = import::import_install(PathBuf::from(&path), current, "reality-manifest".to_string(), tx, control).await;
+
+        match (&result, previous) {
+            (Ok(report), _) => {
+                let state = match report.is_current_build {
+                    true => InstallState::Installed,
+                    false => InstallState::UpdateAvailable,
+                };
+                set_install_state(&path, state).await;
+                share_install(&path).await;
+            }
+            (Err(_), Some(previous)) => {
+                set_install_state(&path, previous).await;
+            }
+            _ => {}
         }
-        
+
+        result
+    })
+    .await
+}
+
+/// Moves an install to another folder or drive, keeping the download instead of starting over
+#[tauri::command]
+pub async fn move_install(
+    target_dir: String,
+    install_id: Option<String>,
+    if_game_running: Option<GameRunningPolicy>,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<(), DownloadError> {
+    let install_dir = resolve_install(install_id).await.map_err(|_| DownloadError::UnexpectedError)?.installation_location;
+
+    manager.run_guarded(OperationKind::Move, install_dir.clone(), if_game_running.unwrap_or_default(), |control| async move {
+        control.progress.lock().await.clear();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(128);
+
+        let progress_handle = control.progress.clone();
+        tokio::spawn(async move {
+            while let Some(progress) = rx.recv().await {
+                progress_handle.lock().await.push(progress);
+            }
+        });
+
+        relocate::move_install(PathBuf::from(&install_dir), PathBuf::from(&target_dir), tx, control).await?;
+
+        // The references still point into the old folder
+        if let Err(e) = release_install(&PathBuf::from(&install_dir)).await {
+            eprintln!("Couldn't release the old folder from the shared store: {}", e);
+        }
+        share_install(&target_dir).await;
         Ok(())
     })
+    .await
 }
 
 fn remove_empty_dirs_recursive<'a>(
@@ -592,6 +1167,12 @@ pub async fn update_installed_object_by_artifact_id(
     update_object_by_artifact_id(object).await
 }
 
+/// Lists every install, each with its own build version, location and state
+#[tauri::command]
+pub async fn list_installs() -> Result<Vec<InstalledObject>, ConfigError> {
+    get_installed_objects().await
+}
+
 /// Caches an installed game so we know which games are installed and at what version
 #[tauri::command]
 pub async fn push_installed_object(object: InstalledObject) -> Result<(), ConfigError> {
@@ -604,12 +1185,20 @@ pub fn get_drives() -> Vec<InstallLocation> {
     get_install_locations()
 }
 
-/// Launches Fortnite with the cached download path and account information (generating an exchange code)
+/// Launches Fortnite from an install, or the first one, with the account information (generating an exchange code)
 #[tauri::command]
-pub async fn launch_game() -> Result<(), LaunchError> {
+pub async fn launch_game(
+    install_id: Option<String>,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<(), LaunchError> {
+    let install_dir = resolve_install(install_id).await?.installation_location;
+
+    // Files an install, update or repair is still writing would crash the game, or get locked by it
+    ensure_can_launch(&manager, &install_dir)?;
+
     // Create launch configuration
     let config = LaunchConfig {
-        game_path: PathBuf::from(get_object_by_artifact_id(Services::CATALOG_ID).await?.installation_location),
+        game_path: PathBuf::from(install_dir),
         launch_args: vec![
             "-AUTH_LOGIN=unused".to_string(),
             format!("-AUTH_PASSWORD={}", generate_exchange().await?),
@@ -688,4 +1277,53 @@ pub async fn decline_friend_request(friend_account_id: String) -> Result<(), Fri
 #[tauri::command]
 pub async fn get_game_information() -> Result<GameInfo, GameInfoError> {
     fetch_current_game_data().await
-}
\ No newline at end of file
+}
+
+/// Lists the queued and running install, update, verify, repair, move and uninstall operations
+#[tauri::command]
+pub fn list_operations(manager: State<'_, Arc<OperationManager>>) -> Vec<OperationInfo> {
+    manager.list()
+}
+
+/// Lists the finished operations, newest first
+#[tauri::command]
+pub fn get_operation_history(manager: State<'_, Arc<OperationManager>>) -> Vec<OperationInfo> {
+    manager.history()
+}
+
+/// Cancels a queued or running operation by id
+#[tauri::command]
+pub fn cancel_operation(id: u64, manager: State<'_, Arc<OperationManager>>) -> Result<(), OperationError> {
+    manager.cancel(id)
+}
+
+/// Returns the state of an install, or of the first one
+#[tauri::command]
+pub async fn get_game_install_state(install_id: Option<String>) -> InstallState {
+    resolve_install(install_id)
+        .await
+        .map(|installed| installed.state)
+        .unwrap_or_default()
+}
+
+/// Lists installs whose operation was cut short by a crash or shutdown, with whether to offer resuming or repairing them
+#[tauri::command]
+pub fn get_interrupted_operations() -> Vec<InterruptedOperation> {
+    load_interrupted()
+}
+
+/// Stops offering to resume or repair an install
+#[tauri::command]
+pub fn dismiss_interrupted_operation(install_dir: String) {
+    dismiss_interrupted(&install_dir)
+}
+
+/// Changes the priority of a queued operation. Higher priorities run first.
+#[tauri::command]
+pub fn reprioritize_operation(
+    id: u64,
+    priority: i32,
+    manager: State<'_, Arc<OperationManager>>,
+) -> Result<(), OperationError> {
+    manager.reprioritize(id, priority)
+}
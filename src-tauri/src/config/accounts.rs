@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::auth::AccountInfo;
+use crate::config::credentials::{delete_secret, load_secret, save_secret};
+use crate::config::install_state::unix_now;
+use crate::config::ConfigError;
+
+/// An account that signs in again from its refresh token, without the password. The token itself is kept in the
+/// credential store.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RememberedAccount {
+    #[serde(rename = "AccountId")] pub account_id: String,
+    #[serde(rename = "DisplayName")] pub display_name: String,
+    /// Unix time the account was last signed in to or switched to
+    #[serde(rename = "LastUsed", default)] pub last_used: u64,
+    /// Only read, from files written before tokens moved to the credential store
+    #[serde(rename = "RefreshToken", default, skip_serializing)] plaintext_token: String,
+}
+
+/// Name of an account's refresh token in the credential store
+fn token_key(account_id: &str) -> String {
+    format!("RefreshToken.{}", account_id)
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RememberedAccounts {
+    #[serde(rename = "Accounts", default)] pub accounts: Vec<RememberedAccount>,
+}
+
+fn get_remembered_accounts_path() -> io::Result<PathBuf> {
+    let local_app_data = std::env::var("LOCALAPPDATA").map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "LOCALAPPDATA environment variable not found",
+        )
+    })?;
+
+    Ok(PathBuf::from(local_app_data)
+        .join("RealityLauncher")
+        .join("RememberedAccounts.dat"))
+}
+
+async fn read_remembered_accounts() -> Result<RememberedAccounts, ConfigError> {
+    let path = get_remembered_accounts_path()?;
+    match fs::read_to_string(path).await {
+        Ok(content) => {
+            let mut data: RememberedAccounts = serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if data.accounts.iter().any(|account| !account.plaintext_token.is_empty()) {
+                migrate_plaintext_tokens(&mut data).await?;
+            }
+            Ok(data)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(RememberedAccounts::default()),
+        Err(e) => Err(ConfigError::IoError(e)),
+    }
+}
+
+/// Moves tokens saved in plain text to the credential store, then rewrites the file without them
+async fn migrate_plaintext_tokens(data: &mut RememberedAccounts) -> Result<(), ConfigError> {
+    for account in data.accounts.iter_mut().filter(|account| !account.plaintext_token.is_empty()) {
+        save_secret(&token_key(&account.account_id), &account.plaintext_token)?;
+        account.plaintext_token.clear();
+    }
+
+    write_remembered_accounts(data).await?;
+    println!("Moved the saved logins to the credential store");
+    Ok(())
+}
+
+async fn write_remembered_accounts(data: &RememberedAccounts) -> Result<(), ConfigError> {
+    let path = get_remembered_accounts_path()?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let content = serde_json::to_string_pretty(data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, content)
+        .await
+        .map_err(ConfigError::IoError)
+}
+
+/// Remembered accounts, the most recently used first
+pub async fn get_remembered_accounts() -> Result<Vec<RememberedAccount>, ConfigError> {
+    let mut accounts = read_remembered_accounts().await?.accounts;
+    accounts.sort_by_key(|account| std::cmp::Reverse(account.last_used));
+    Ok(accounts)
+}
+
+pub async fn get_remembered_account(account_id: &str) -> Result<RememberedAccount, ConfigError> {
+    read_remembered_accounts()
+        .await?
+        .accounts
+        .into_iter()
+        .find(|account| account.account_id == account_id)
+        .ok_or_else(|| ConfigError::AccountNotRemembered(account_id.to_string()))
+}
+
+/// The refresh token an account signs in again with
+pub async fn get_remembered_token(account_id: &str) -> Result<String, ConfigError> {
+    load_secret(&token_key(account_id))?.ok_or_else(|| ConfigError::AccountNotRemembered(account_id.to_string()))
+}
+
+/// Remembers an account, or updates it when it already is, and marks it as the last one used
+pub async fn remember_account(account_info: &AccountInfo) -> Result<(), ConfigError> {
+    save_secret(&token_key(&account_info.account_id), &account_info.refresh_token)?;
+
+    let mut data = read_remembered_accounts().await?;
+    let remembered = RememberedAccount {
+        account_id: account_info.account_id.clone(),
+        display_name: account_info.display_name.clone(),
+        last_used: unix_now(),
+        plaintext_token: String::new(),
+    };
+
+    match data
+        .accounts
+        .iter_mut()
+        .find(|account| account.account_id == remembered.account_id)
+    {
+        Some(existing) => *existing = remembered,
+        None => data.accounts.push(remembered),
+    }
+
+    write_remembered_accounts(&data).await
+}
+
+/// Saves the refresh token a refresh replaced, if the account is remembered. Doesn't count as using the account.
+pub async fn update_remembered_token(account_info: &AccountInfo) -> Result<(), ConfigError> {
+    let mut data = read_remembered_accounts().await?;
+    let Some(existing) = data
+        .accounts
+        .iter_mut()
+        .find(|account| account.account_id == account_info.account_id)
+    else {
+        return Ok(());
+    };
+
+    save_secret(&token_key(&account_info.account_id), &account_info.refresh_token)?;
+    existing.display_name = account_info.display_name.clone();
+    write_remembered_accounts(&data).await
+}
+
+pub async fn forget_account(account_id: &str) -> Result<(), ConfigError> {
+    delete_secret(&token_key(account_id))?;
+
+    let mut data = read_remembered_accounts().await?;
+
+    let initial_len = data.accounts.len();
+    data.accounts.retain(|account| account.account_id != account_id);
+    if data.accounts.len() == initial_len {
+        return Ok(());
+    }
+
+    write_remembered_accounts(&data).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::credentials::tests::use_test_data_dir;
+
+    #[tokio::test]
+    async fn moves_plaintext_tokens_to_the_credential_store() {
+        use_test_data_dir();
+        let path = get_remembered_accounts_path().unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            &path,
+            r#"{"Accounts":[{"AccountId":"migrated","DisplayName":"Migrated","LastUsed":1,"RefreshToken":"plaintext-token"}]}"#,
+        )
+        .unwrap();
+
+        let accounts = get_remembered_accounts().await.unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].display_name, "Migrated");
+
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("plaintext-token"));
+        assert_eq!(get_remembered_token("migrated").await.unwrap(), "plaintext-token");
+
+        forget_account("migrated").await.unwrap();
+        assert!(get_remembered_token("migrated").await.is_err());
+    }
+}
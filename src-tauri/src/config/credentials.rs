@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use aes_gcm::aead::{Aead, KeyInit};
+#[cfg(not(windows))]
+use aes_gcm::aead::{AeadCore, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::ConfigError;
+
+/// Name the launcher's secrets are saved under in the OS keychain
+const SERVICE_NAME: &str = "RealityLauncher";
+
+const NONCE_LEN: usize = 12;
+
+/// Marks the entries of the encrypted file sealed with DPAPI
+const DPAPI_PREFIX: &str = "dpapi:";
+
+/// Somewhere to keep secrets, like refresh tokens, out of plain text
+pub trait CredentialStore: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn get(&self, key: &str) -> Result<Option<String>, ConfigError>;
+    fn set(&self, key: &str, secret: &str) -> Result<(), ConfigError>;
+    fn delete(&self, key: &str) -> Result<(), ConfigError>;
+}
+
+/// Windows Credential Manager or the macOS Keychain, which only the signed in user can read
+#[cfg(any(windows, target_os = "macos"))]
+pub struct KeychainStore;
+
+#[cfg(any(windows, target_os = "macos"))]
+impl KeychainStore {
+    fn entry(key: &str) -> Result<keyring::Entry, ConfigError> {
+        keyring::Entry::new(SERVICE_NAME, key).map_err(|e| ConfigError::CredentialStoreError(e.to_string()))
+    }
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+impl CredentialStore for KeychainStore {
+    fn name(&self) -> &'static str {
+        "keychain"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        match Self::entry(key)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(ConfigError::CredentialStoreError(e.to_string())),
+        }
+    }
+
+    fn set(&self, key: &str, secret: &str) -> Result<(), ConfigError> {
+        Self::entry(key)?
+            .set_password(secret)
+            .map_err(|e| ConfigError::CredentialStoreError(e.to_string()))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        match Self::entry(key)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(ConfigError::CredentialStoreError(e.to_string())),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct EncryptedCredentials {
+    /// Random per file, so the key isn't the same on every machine with the same names
+    #[serde(rename = "Salt", default)] salt: String,
+    /// Base64 of the nonce followed by the encrypted secret, or of the DPAPI blob after `DPAPI_PREFIX`
+    #[serde(rename = "Entries", default)] entries: HashMap<String, String>,
+}
+
+/// Encrypted file, for when the keychain can't be used. On Windows each secret is sealed with DPAPI, which ties it to
+/// the signed in user's login. Elsewhere it's AES-GCM with a key made from the machine id and user name, which aren't
+/// secret, so that only keeps the tokens from being read at a glance: anyone with the file and those names can
+/// decrypt it. Neither keeps out other programs running as the same user.
+pub struct EncryptedFileStore {
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileStore {
+    fn path() -> io::Result<PathBuf> {
+        // LOCALAPPDATA is only set on Windows, elsewhere the platform's local data folder is used
+        let local_app_data = std::env::var_os("LOCALAPPDATA")
+            .map(PathBuf::from)
+            .or_else(dirs::data_local_dir)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No local data folder found"))?;
+
+        Ok(local_app_data
+            .join("RealityLauncher")
+            .join("Credentials.dat"))
+    }
+
+    fn read() -> Result<EncryptedCredentials, ConfigError> {
+        match fs::read_to_string(Self::path()?) {
+            Ok(content) => Ok(serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(EncryptedCredentials::default()),
+            Err(e) => Err(ConfigError::IoError(e)),
+        }
+    }
+
+    /// Written to a temp file first, so a crash mid-write never loses the other secrets
+    fn write(data: &EncryptedCredentials) -> Result<(), ConfigError> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = path.with_extension("dat.tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Seals a secret with DPAPI, marked so it's told apart from AES-GCM entries written before
+    #[cfg(windows)]
+    fn seal(_data: &mut EncryptedCredentials, secret: &str) -> Result<String, ConfigError> {
+        let protected = dpapi::protect(secret.as_bytes()).map_err(|e| ConfigError::CredentialStoreError(e.to_string()))?;
+        Ok(format!("{}{}", DPAPI_PREFIX, BASE64_STANDARD.encode(protected)))
+    }
+
+    /// Seals a secret with AES-GCM, as the nonce followed by the encrypted secret
+    #[cfg(not(windows))]
+    fn seal(data: &mut EncryptedCredentials, secret: &str) -> Result<String, ConfigError> {
+        if data.salt.is_empty() {
+            data.salt = hex::encode(Aes256Gcm::generate_nonce(&mut OsRng));
+        }
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Self::cipher(&data.salt)?
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|e| ConfigError::CredentialStoreError(e.to_string()))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(BASE64_STANDARD.encode(sealed))
+    }
+
+    fn cipher(salt: &str) -> Result<Aes256Gcm, ConfigError> {
+        let mut hasher = Sha256::new();
+        hasher.update(SERVICE_NAME.as_bytes());
+        hasher.update(machine_id().as_bytes());
+        hasher.update(user_name().as_bytes());
+        hasher.update(salt.as_bytes());
+
+        Aes256Gcm::new_from_slice(&hasher.finalize()).map_err(|e| ConfigError::CredentialStoreError(e.to_string()))
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn name(&self) -> &'static str {
+        "encrypted file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let data = Self::read()?;
+        let Some(encoded) = data.entries.get(key) else {
+            return Ok(None);
+        };
+
+        if let Some(protected) = encoded.strip_prefix(DPAPI_PREFIX) {
+            let protected = BASE64_STANDARD
+                .decode(protected)
+                .map_err(|e| ConfigError::CredentialStoreError(e.to_string()))?;
+            let plaintext = dpapi::unprotect(&protected)
+                .map_err(|_| ConfigError::CredentialStoreError(format!("The saved secret for {} can't be decrypted", key)))?;
+            return String::from_utf8(plaintext)
+                .map(Some)
+                .map_err(|e| ConfigError::CredentialStoreError(e.to_string()));
+        }
+
+        let sealed = BASE64_STANDARD
+            .decode(encoded)
+            .map_err(|e| ConfigError::CredentialStoreError(e.to_string()))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(ConfigError::CredentialStoreError(format!("The saved secret for {} is cut short", key)));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        // Fails when the file came from another machine or user, or was tampered with
+        let plaintext = Self::cipher(&data.salt)?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| ConfigError::CredentialStoreError(format!("The saved secret for {} can't be decrypted", key)))?;
+
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|e| ConfigError::CredentialStoreError(e.to_string()))
+    }
+
+    fn set(&self, key: &str, secret: &str) -> Result<(), ConfigError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut data = Self::read()?;
+        let sealed = Self::seal(&mut data, secret)?;
+        data.entries.insert(key.to_string(), sealed);
+
+        Self::write(&data)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut data = Self::read()?;
+        if data.entries.remove(key).is_none() {
+            return Ok(());
+        }
+        Self::write(&data)
+    }
+}
+
+/// Windows Data Protection, which encrypts with a key only the signed in user's login unlocks
+#[cfg(windows)]
+mod dpapi {
+    use std::io;
+    use std::ptr;
+
+    use winapi::um::dpapi::{CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN};
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::wincrypt::DATA_BLOB;
+
+    pub fn protect(data: &[u8]) -> io::Result<Vec<u8>> {
+        run(data, true)
+    }
+
+    pub fn unprotect(data: &[u8]) -> io::Result<Vec<u8>> {
+        run(data, false)
+    }
+
+    fn run(data: &[u8], protect: bool) -> io::Result<Vec<u8>> {
+        let mut input = DATA_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+        let mut output = DATA_BLOB {
+            cbData: 0,
+            pbData: ptr::null_mut(),
+        };
+
+        unsafe {
+            let succeeded = match protect {
+                true => CryptProtectData(
+                    &mut input,
+                    ptr::null(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    CRYPTPROTECT_UI_FORBIDDEN,
+                    &mut output,
+                ),
+                false => CryptUnprotectData(
+                    &mut input,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    CRYPTPROTECT_UI_FORBIDDEN,
+                    &mut output,
+                ),
+            };
+            if succeeded == 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let result = std::slice::from_raw_parts(output.pbData, output.cbData as usize).to_vec();
+            LocalFree(output.pbData as _);
+            Ok(result)
+        }
+    }
+}
+
+/// DPAPI is Windows only, so a secret sealed with it can't be opened anywhere else
+#[cfg(not(windows))]
+mod dpapi {
+    use std::io;
+
+    pub fn unprotect(_data: &[u8]) -> io::Result<Vec<u8>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "DPAPI is only available on Windows"))
+    }
+}
+
+/// Identifies the machine for the AES-GCM key, so the file doesn't decrypt as it is on another one. It isn't secret.
+fn machine_id() -> String {
+    // Set up by systemd on most Linux distributions
+    if let Ok(id) = fs::read_to_string("/etc/machine-id") {
+        return id.trim().to_string();
+    }
+
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_default()
+}
+
+fn user_name() -> String {
+    std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_default()
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn keychain_store() -> Option<Box<dyn CredentialStore>> {
+    Some(Box::new(KeychainStore))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn keychain_store() -> Option<Box<dyn CredentialStore>> {
+    None
+}
+
+static CREDENTIAL_STORES: OnceLock<Vec<Box<dyn CredentialStore>>> = OnceLock::new();
+
+/// The stores to try in order: the keychain where there is one, then the encrypted file
+fn credential_stores() -> &'static [Box<dyn CredentialStore>] {
+    CREDENTIAL_STORES.get_or_init(|| {
+        let file_store: Box<dyn CredentialStore> = Box::new(EncryptedFileStore { lock: Mutex::new(()) });
+        keychain_store().into_iter().chain([file_store]).collect()
+    })
+}
+
+/// Saves a secret to the first store that takes it and removes it from the others, so no stale copy is left behind
+pub fn save_secret(key: &str, secret: &str) -> Result<(), ConfigError> {
+    let mut last_error = None;
+    let mut saved = false;
+
+    for store in credential_stores() {
+        if saved {
+            if let Err(e) = store.delete(key) {
+                eprintln!("Couldn't remove the old copy of {} from the {}: {}", key, store.name(), e);
+            }
+            continue;
+        }
+
+        match store.set(key, secret) {
+            Ok(()) => saved = true,
+            Err(e) => {
+                eprintln!("Couldn't save {} to the {}, trying the next store: {}", key, store.name(), e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    match saved {
+        true => Ok(()),
+        false => Err(last_error.unwrap_or(ConfigError::UnexpectedError)),
+    }
+}
+
+/// Loads a secret from the first store that has it
+pub fn load_secret(key: &str) -> Result<Option<String>, ConfigError> {
+    let mut last_error = None;
+
+    for store in credential_stores() {
+        match store.get(key) {
+            Ok(Some(secret)) => return Ok(Some(secret)),
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Couldn't read {} from the {}: {}", key, store.name(), e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(e),
+        None => Ok(None),
+    }
+}
+
+pub fn delete_secret(key: &str) -> Result<(), ConfigError> {
+    let mut result = Ok(());
+    for store in credential_stores() {
+        if let Err(e) = store.delete(key) {
+            eprintln!("Couldn't remove {} from the {}: {}", key, store.name(), e);
+            result = Err(e);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Points LOCALAPPDATA at a folder of this test run. The variable is process wide, so every test shares the folder
+    /// and uses keys of its own.
+    pub(crate) fn use_test_data_dir() {
+        static DIR: OnceLock<PathBuf> = OnceLock::new();
+        DIR.get_or_init(|| {
+            let dir = std::env::temp_dir().join(format!("reality-launcher-test-{}", std::process::id()));
+            std::env::set_var("LOCALAPPDATA", &dir);
+            dir
+        });
+    }
+
+    #[test]
+    fn saves_loads_and_deletes_a_secret() {
+        use_test_data_dir();
+        let key = "Test.RoundTrip";
+
+        save_secret(key, "first-secret").unwrap();
+        assert_eq!(load_secret(key).unwrap().as_deref(), Some("first-secret"));
+
+        save_secret(key, "second-secret").unwrap();
+        assert_eq!(load_secret(key).unwrap().as_deref(), Some("second-secret"));
+
+        delete_secret(key).unwrap();
+        assert_eq!(load_secret(key).unwrap(), None);
+    }
+
+    #[test]
+    fn encrypted_file_never_holds_the_secret_in_plain_text() {
+        use_test_data_dir();
+        let store = EncryptedFileStore { lock: Mutex::new(()) };
+        let key = "Test.EncryptedFile";
+
+        store.set(key, "file-secret").unwrap();
+        assert_eq!(store.get(key).unwrap().as_deref(), Some("file-secret"));
+
+        let content = fs::read_to_string(EncryptedFileStore::path().unwrap()).unwrap();
+        assert!(!content.contains("file-secret"));
+
+        store.delete(key).unwrap();
+        assert_eq!(store.get(key).unwrap(), None);
+    }
+}
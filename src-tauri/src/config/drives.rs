@@ -1,3 +1,5 @@
+use std::path::{Path, PathBuf};
+
 use serde::Serialize;
 use sysinfo::{DiskExt, System, SystemExt};
 
@@ -68,4 +70,22 @@ pub fn get_install_locations() -> Vec<InstallLocation> {
     }
 
     locations
-}
\ No newline at end of file
+}
+
+/// The mount point of the disk a path is on, and how many bytes are free there. The path doesn't need to exist yet.
+pub fn disk_for_path(path: &Path) -> Option<(PathBuf, u64)> {
+    let mut sys = System::new_all();
+    sys.refresh_disks_list();
+
+    let target = path.to_string_lossy().replace('/', "\\").to_lowercase();
+
+    sys.disks()
+        .iter()
+        .filter(|disk| {
+            let mount_point = disk.mount_point().to_string_lossy().replace('/', "\\").to_lowercase();
+            target.starts_with(&mount_point)
+        })
+        // Nested mount points are more specific than the drive they sit on
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| (disk.mount_point().to_path_buf(), disk.available_space()))
+}
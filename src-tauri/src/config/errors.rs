@@ -1,4 +1,5 @@
 use crate::auth::AuthError;
+use crate::config::install_state::InstallState;
 
 use thiserror::Error;
 
@@ -13,6 +14,15 @@ pub enum ConfigError {
     #[error("{0}")]
     AuthError(#[from] AuthError),
 
+    #[error("Account {0} isn't remembered, please log in to it")]
+    AccountNotRemembered(String),
+
+    #[error("Credential store error: {0}")]
+    CredentialStoreError(String),
+
+    #[error("The install can't go from {0:?} to {1:?}")]
+    InvalidStateTransition(InstallState, InstallState),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -0,0 +1,270 @@
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::auth::Services;
+use crate::config::installed::{get_object_by_location, install_id_for_location, update_installed, InstalledObject};
+use crate::config::ConfigError;
+
+/// Event the frontend listens on for install state changes
+pub const INSTALL_STATE_EVENT: &str = "install-state-changed";
+
+static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InstallState {
+    #[default]
+    NotInstalled,
+    Installing,
+    Installed,
+    UpdateAvailable,
+    Updating,
+    Verifying,
+    NeedsRepair,
+    Uninstalling,
+}
+
+impl InstallState {
+    /// Installs recorded before states existed were only ever written once they finished
+    pub fn legacy() -> Self {
+        InstallState::Installed
+    }
+
+    /// Whether the game files are there to play, possibly needing an update or a repair first
+    pub fn is_installed(&self) -> bool {
+        matches!(
+            self,
+            InstallState::Installed
+                | InstallState::UpdateAvailable
+                | InstallState::Updating
+                | InstallState::Verifying
+                | InstallState::NeedsRepair
+        )
+    }
+
+    pub fn can_become(&self, next: InstallState) -> bool {
+        use InstallState::*;
+
+        if *self == next {
+            return true;
+        }
+
+        match next {
+            NotInstalled => matches!(self, Installing | Uninstalling),
+            Installing => matches!(self, NotInstalled | NeedsRepair),
+            // Uninstalling covers an uninstall that stopped before deleting anything
+            Installed => matches!(self, Installing | UpdateAvailable | Updating | Verifying | NeedsRepair | Uninstalling),
+            // Installing covers importing a build that is no longer the live one
+            UpdateAvailable => matches!(self, Installing | Installed | Updating | Verifying | Uninstalling),
+            Updating => matches!(self, Installed | UpdateAvailable | NeedsRepair),
+            Verifying => matches!(self, Installed | UpdateAvailable | NeedsRepair),
+            // Anything that was on disk can end up broken
+            NeedsRepair => *self != NotInstalled,
+            Uninstalling => *self != NotInstalled,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InstallStateChange {
+    pub install_id: String,
+    pub artifact_id: String,
+    pub install_dir: String,
+    pub previous: InstallState,
+    pub state: InstallState,
+}
+
+/// Lets state changes reach the frontend. Changes made before this only get saved.
+pub fn attach_app_handle(handle: AppHandle) {
+    let _ = APP_HANDLE.set(handle);
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The state of the install at a location, which is NotInstalled when nothing is recorded there
+pub async fn get_install_state(install_dir: &str) -> InstallState {
+    get_object_by_location(install_dir)
+        .await
+        .map(|object| object.state)
+        .unwrap_or_default()
+}
+
+/// Moves the install at a location to a new state. Every state change goes through here, gets saved to
+/// `LauncherInstalled.dat` and is sent to the frontend. `update` can record details that come with the change.
+/// Returns the state the install was in before.
+pub async fn transition(
+    install_dir: &str,
+    next: InstallState,
+    update: impl FnOnce(&mut InstalledObject),
+) -> Result<InstallState, ConfigError> {
+    // The check and the write happen under one lock, so a change in between can't be overwritten
+    let (previous, install_id, artifact_id, install_dir) = update_installed(|data| {
+        let position = data.position_of_location(install_dir);
+        let previous = position.map(|i| data.installation_list[i].state).unwrap_or_default();
+
+        if !previous.can_become(next) {
+            return Err(ConfigError::InvalidStateTransition(previous, next));
+        }
+
+        let (install_id, artifact_id, install_dir) = match position.map(|i| &data.installation_list[i]) {
+            Some(object) => (
+                object.install_id.clone(),
+                object.artifact_id.clone(),
+                object.installation_location.clone(),
+            ),
+            None => (
+                install_id_for_location(install_dir),
+                Services::CATALOG_ID.to_string(),
+                install_dir.to_string(),
+            ),
+        };
+
+        match position {
+            Some(i) if next == InstallState::NotInstalled => {
+                data.installation_list.remove(i);
+            }
+            None if next == InstallState::NotInstalled => {}
+            Some(i) => {
+                let object = &mut data.installation_list[i];
+                object.state = next;
+                update(object);
+            }
+            None => {
+                // A fresh install gets a record straight away, the build details are filled in once it finishes
+                let mut object = InstalledObject {
+                    install_id: install_id.clone(),
+                    installation_location: install_dir.clone(),
+                    namespace_id: String::new(),
+                    item_id: String::new(),
+                    artifact_id: artifact_id.clone(),
+                    app_version: String::new(),
+                    app_name: String::new(),
+                    rolled_back_from: None,
+                    state: next,
+                    manifest_hash: None,
+                    install_tags: Vec::new(),
+                    last_verified: None,
+                };
+                update(&mut object);
+                data.installation_list.push(object);
+            }
+        }
+
+        Ok((previous, install_id, artifact_id, install_dir))
+    })
+    .await?;
+
+    if previous != next {
+        println!("Install state of {}: {:?} -> {:?}", install_dir, previous, next);
+        if let Some(handle) = APP_HANDLE.get() {
+            let change = InstallStateChange {
+                install_id,
+                artifact_id,
+                install_dir,
+                previous,
+                state: next,
+            };
+            if let Err(e) = handle.emit(INSTALL_STATE_EVENT, change) {
+                eprintln!("Couldn't send the install state to the frontend: {}", e);
+            }
+        }
+    }
+
+    Ok(previous)
+}
+
+/// Flags an installed build that is no longer the live one, and clears the flag once it is again
+pub async fn refresh_update_available(
+    installed: &InstalledObject,
+    live_version: &str,
+) -> Result<InstallState, ConfigError> {
+    let next = match (installed.state, installed.app_version == live_version) {
+        (InstallState::Installed, false) => InstallState::UpdateAvailable,
+        (InstallState::UpdateAvailable, true) => InstallState::Installed,
+        (state, _) => return Ok(state),
+    };
+
+    transition(&installed.installation_location, next, |_| {}).await?;
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InstallState::{self, *};
+
+    const ALL: [InstallState; 8] = [
+        NotInstalled,
+        Installing,
+        Installed,
+        UpdateAvailable,
+        Updating,
+        Verifying,
+        NeedsRepair,
+        Uninstalling,
+    ];
+
+    #[test]
+    fn follows_an_install_through_its_life() {
+        let steps = [
+            NotInstalled,
+            Installing,
+            Installed,
+            UpdateAvailable,
+            Updating,
+            Installed,
+            Verifying,
+            NeedsRepair,
+            Verifying,
+            Installed,
+            Uninstalling,
+            NotInstalled,
+        ];
+
+        for pair in steps.windows(2) {
+            assert!(pair[0].can_become(pair[1]), "{:?} -> {:?}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn staying_put_is_always_allowed() {
+        for state in ALL {
+            assert!(state.can_become(state), "{:?}", state);
+        }
+    }
+
+    #[test]
+    fn nothing_but_an_install_starts_from_nothing() {
+        for next in ALL.into_iter().filter(|next| !matches!(next, NotInstalled | Installing)) {
+            assert!(!NotInstalled.can_become(next), "NotInstalled -> {:?}", next);
+        }
+    }
+
+    #[test]
+    fn files_only_go_away_through_an_uninstall() {
+        for state in [Installed, UpdateAvailable, Updating, Verifying, NeedsRepair] {
+            assert!(!state.can_become(NotInstalled), "{:?} -> NotInstalled", state);
+            assert!(state.can_become(Uninstalling), "{:?} -> Uninstalling", state);
+        }
+    }
+
+    #[test]
+    fn an_uninstall_that_deleted_nothing_goes_back() {
+        assert!(Uninstalling.can_become(Installed));
+        assert!(Uninstalling.can_become(UpdateAvailable));
+        assert!(!Uninstalling.can_become(Updating));
+    }
+
+    #[test]
+    fn busy_states_do_not_hand_over_to_each_other() {
+        assert!(!Updating.can_become(Verifying));
+        assert!(!Verifying.can_become(Updating));
+        assert!(!Installing.can_become(Updating));
+    }
+}
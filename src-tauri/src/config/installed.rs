@@ -1,18 +1,34 @@
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 use std::io;
 use std::path::PathBuf;
 use tokio::fs;
+use tokio::sync::Mutex;
 
+use crate::config::install_state::InstallState;
 use crate::config::ConfigError;
+use crate::operations::operation_info::normalize_install_dir;
+
+/// Serializes changes to `LauncherInstalled.dat`, so two changes landing at the same time can't undo each other
+static INSTALLED_FILE_LOCK: Mutex<()> = Mutex::const_new(());
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InstalledObject {
+    /// Tells apart installs of the same app. Installs recorded before there could be several use their artifact id.
+    #[serde(rename = "InstallId", default)] pub install_id: String,
     #[serde(rename = "InstallLocation")] pub installation_location: String,
     #[serde(rename = "NamespaceId")] pub namespace_id: String,
     #[serde(rename = "ItemId")] pub item_id: String,
     #[serde(rename = "ArtifactId")] pub artifact_id: String,
     #[serde(rename = "AppVersion")] pub app_version: String,
     #[serde(rename = "AppName")] pub app_name: String,
+    #[serde(rename = "RolledBackFrom", default, skip_serializing_if = "Option::is_none")] pub rolled_back_from: Option<String>,
+    #[serde(rename = "State", default = "InstallState::legacy")] pub state: InstallState,
+    /// SHA1 of the manifest the installed files were built from
+    #[serde(rename = "ManifestHash", default, skip_serializing_if = "Option::is_none")] pub manifest_hash: Option<String>,
+    #[serde(rename = "InstallTags", default, skip_serializing_if = "Vec::is_empty")] pub install_tags: Vec<String>,
+    /// Unix time of the last verify that found nothing wrong, or repaired everything it found
+    #[serde(rename = "LastVerified", default, skip_serializing_if = "Option::is_none")] pub last_verified: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -20,6 +36,16 @@ pub struct LauncherInstalled {
     #[serde(rename = "InstallationList")] pub installation_list: Vec<InstalledObject>,
 }
 
+impl LauncherInstalled {
+    /// Where the install at a location is in the list
+    pub fn position_of_location(&self, location: &str) -> Option<usize> {
+        let location = normalize_install_dir(location);
+        self.installation_list
+            .iter()
+            .position(|obj| normalize_install_dir(&obj.installation_location) == location)
+    }
+}
+
 fn get_launcher_installed_path() -> io::Result<PathBuf> {
     let local_app_data = std::env::var("LOCALAPPDATA").map_err(|_| {
         io::Error::new(
@@ -37,8 +63,11 @@ async fn read_launcher_installed_data() -> Result<LauncherInstalled, ConfigError
     let path = get_launcher_installed_path()?;
     match fs::read_to_string(path).await {
         Ok(content) => {
-            let data = serde_json::from_str(&content)
+            let mut data: LauncherInstalled = serde_json::from_str(&content)
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            for object in data.installation_list.iter_mut().filter(|obj| obj.install_id.is_empty()) {
+                object.install_id = object.artifact_id.clone();
+            }
             Ok(data)
         }
         Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(LauncherInstalled::default()),
@@ -59,6 +88,30 @@ async fn write_launcher_installed_data(data: &LauncherInstalled) -> Result<(), C
         .map_err(|e| ConfigError::IoError(e))
 }
 
+/// Reads the installs, changes them and writes them back, without another change slipping in between.
+/// Nothing is written if `change` fails.
+pub async fn update_installed<T>(
+    change: impl FnOnce(&mut LauncherInstalled) -> Result<T, ConfigError>,
+) -> Result<T, ConfigError> {
+    let _guard = INSTALLED_FILE_LOCK.lock().await;
+
+    let mut data = read_launcher_installed_data().await?;
+    let result = change(&mut data)?;
+    write_launcher_installed_data(&data).await?;
+
+    Ok(result)
+}
+
+pub async fn get_installed_objects() -> Result<Vec<InstalledObject>, ConfigError> {
+    Ok(read_launcher_installed_data().await?.installation_list)
+}
+
+/// The id a new install at a location gets, which stays the same if it is ever registered again there
+pub fn install_id_for_location(location: &str) -> String {
+    hex::encode(Sha1::digest(normalize_install_dir(location).as_bytes()))[..12].to_string()
+}
+
+/// Returns the first install of an app, which is the one used when no install is picked
 pub async fn get_object_by_artifact_id(artifact_id: &str) -> Result<InstalledObject, ConfigError> {
     let data = read_launcher_installed_data().await?;
     data.installation_list
@@ -75,54 +128,130 @@ pub async fn get_object_by_artifact_id(artifact_id: &str) -> Result<InstalledObj
 pub async fn update_object_by_artifact_id(
     updated_object: InstalledObject,
 ) -> Result<(), ConfigError> {
-    let mut data = read_launcher_installed_data().await?;
-    let artifact_id = updated_object.artifact_id.clone();
-
-    if let Some(object_ref) = data
-        .installation_list
-        .iter_mut()
-        .find(|obj| obj.artifact_id == artifact_id)
-    {
-        *object_ref = updated_object;
-    } else {
-        return Err(ConfigError::IoError(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("Cannot update: Artifact ID '{}' not found", artifact_id),
-        )));
-    }
+    update_installed(|data| {
+        let artifact_id = updated_object.artifact_id.clone();
 
-    write_launcher_installed_data(&data).await
+        if let Some(object_ref) = data
+            .installation_list
+            .iter_mut()
+            .find(|obj| obj.artifact_id == artifact_id)
+        {
+            // Callers that predate install ids send objects without one
+            let install_id = std::mem::take(&mut object_ref.install_id);
+            *object_ref = updated_object;
+            if object_ref.install_id.is_empty() {
+                object_ref.install_id = install_id;
+            }
+            Ok(())
+        } else {
+            Err(ConfigError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Cannot update: Artifact ID '{}' not found", artifact_id),
+            )))
+        }
+    })
+    .await
 }
 
 pub async fn remove_object_by_artifact_id(artifact_id: &str) -> Result<(), ConfigError> {
-    let mut data = read_launcher_installed_data().await?;
-    
-    let initial_len = data.installation_list.len();
-    data.installation_list.retain(|obj| obj.artifact_id != artifact_id);
-    
-    if data.installation_list.len() == initial_len {
-        return Err(ConfigError::IoError(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("Cannot remove: Artifact ID '{}' not found", artifact_id),
-        )));
-    }
-    
-    write_launcher_installed_data(&data).await
+    update_installed(|data| {
+        let initial_len = data.installation_list.len();
+        data.installation_list.retain(|obj| obj.artifact_id != artifact_id);
+
+        if data.installation_list.len() == initial_len {
+            return Err(ConfigError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Cannot remove: Artifact ID '{}' not found", artifact_id),
+            )));
+        }
+        Ok(())
+    })
+    .await
 }
 
-pub async fn add_or_update_object(new_object: InstalledObject) -> Result<(), ConfigError> {
-    let mut data = read_launcher_installed_data().await?;
-    let artifact_id = new_object.artifact_id.clone();
-
-    if let Some(position) = data
-        .installation_list
-        .iter()
-        .position(|obj| obj.artifact_id == artifact_id)
-    {
-        data.installation_list[position] = new_object;
-    } else {
-        data.installation_list.push(new_object);
+pub async fn get_object_by_install_id(install_id: &str) -> Result<InstalledObject, ConfigError> {
+    let data = read_launcher_installed_data().await?;
+    data.installation_list
+        .into_iter()
+        .find(|obj| obj.install_id == install_id)
+        .ok_or_else(|| {
+            ConfigError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Install ID '{}' not found", install_id),
+            ))
+        })
+}
+
+pub async fn get_object_by_location(location: &str) -> Result<InstalledObject, ConfigError> {
+    let location = normalize_install_dir(location);
+    let data = read_launcher_installed_data().await?;
+    data.installation_list
+        .into_iter()
+        .find(|obj| normalize_install_dir(&obj.installation_location) == location)
+        .ok_or_else(|| {
+            ConfigError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No install at '{}'", location),
+            ))
+        })
+}
+
+pub async fn update_object_by_install_id(
+    updated_object: InstalledObject,
+) -> Result<(), ConfigError> {
+    update_installed(|data| {
+        let install_id = updated_object.install_id.clone();
+
+        if let Some(object_ref) = data
+            .installation_list
+            .iter_mut()
+            .find(|obj| obj.install_id == install_id)
+        {
+            *object_ref = updated_object;
+            Ok(())
+        } else {
+            Err(ConfigError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Cannot update: Install ID '{}' not found", install_id),
+            )))
+        }
+    })
+    .await
+}
+
+pub async fn remove_object_by_install_id(install_id: &str) -> Result<(), ConfigError> {
+    update_installed(|data| {
+        let initial_len = data.installation_list.len();
+        data.installation_list.retain(|obj| obj.install_id != install_id);
+
+        if data.installation_list.len() == initial_len {
+            return Err(ConfigError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Cannot remove: Install ID '{}' not found", install_id),
+            )));
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Adds an install, or replaces the one with the same install id. An object without an id is given the one for its location.
+pub async fn add_or_update_object(mut new_object: InstalledObject) -> Result<(), ConfigError> {
+    if new_object.install_id.is_empty() {
+        new_object.install_id = install_id_for_location(&new_object.installation_location);
     }
 
-    write_launcher_installed_data(&data).await
+    update_installed(|data| {
+        if let Some(position) = data
+            .installation_list
+            .iter()
+            .position(|obj| obj.install_id == new_object.install_id)
+        {
+            data.installation_list[position] = new_object;
+        } else {
+            data.installation_list.push(new_object);
+        }
+        Ok(())
+    })
+    .await
 }
@@ -1,7 +1,11 @@
 use crate::auth::{login_user_refresh, AccountInfo};
+use crate::manifest::downloader::hashing::HashPolicy;
 
+pub mod accounts;
+pub mod credentials;
 pub mod drives;
 pub mod errors;
+pub mod install_state;
 pub mod installed;
 
 use std::collections::HashMap;
@@ -11,6 +15,12 @@ use std::path::PathBuf;
 
 pub use errors::ConfigError;
 
+use accounts::{forget_account, get_remembered_accounts, get_remembered_token, remember_account};
+use credentials::{delete_secret, load_secret, save_secret};
+
+/// Name in the credential store of a token saved before accounts were remembered by id, until it signs in again
+const LEGACY_TOKEN_KEY: &str = "RefreshToken.Legacy";
+
 fn get_game_user_config_path() -> io::Result<PathBuf> {
     let local_app_data = std::env::var("LOCALAPPDATA").map_err(|_| {
         io::Error::new(
@@ -77,10 +87,21 @@ fn write_ini_file(
     Ok(())
 }
 
+/// Turns signing in on startup on or off. Turning it on remembers the account, turning it off forgets it.
 pub async fn save_user_login(
     enable_remember_me: bool,
-    refresh_token: String,
+    account_info: &AccountInfo,
 ) -> Result<(), ConfigError> {
+    match enable_remember_me {
+        true => remember_account(account_info).await?,
+        false => forget_account(&account_info.account_id).await?,
+    }
+
+    save_remember_me_value(enable_remember_me).await
+}
+
+/// Whether the most recently used remembered account signs in on startup
+pub async fn save_remember_me_value(enabled: bool) -> Result<(), ConfigError> {
     let config_path = get_game_user_config_path()?;
 
     if let Some(parent) = config_path.parent() {
@@ -94,25 +115,51 @@ pub async fn save_user_login(
         HashMap::new()
     };
 
-    // Update the RememberMe section
+    // Update the RememberMe section, the refresh tokens are in the credential store
     ini_content
         .entry("RememberMe".to_string())
         .or_insert_with(HashMap::new)
-        .insert("Enabled".to_string(), enable_remember_me.to_string());
+        .insert("Enabled".to_string(), enabled.to_string());
+
+    write_ini_file(&config_path, &ini_content)?;
 
-    if enable_remember_me {
-        ini_content
-            .entry("RememberMe".to_string())
-            .or_insert_with(HashMap::new)
-            .insert("Data".to_string(), refresh_token);
+    Ok(())
+}
+
+/// Moves the refresh token older versions saved in plain text to `[RememberMe] Data` into the credential store, along
+/// with any kept with the remembered accounts. Runs on startup, before anything signs in.
+pub async fn migrate_plaintext_credentials() -> Result<(), ConfigError> {
+    // Reading the remembered accounts moves their tokens
+    get_remembered_accounts().await?;
+
+    let config_path = get_game_user_config_path()?;
+    if !config_path.exists() {
+        return Ok(());
     }
 
+    let mut ini_content = parse_ini_file(&config_path)?;
+    let Some(legacy_token) = ini_content
+        .get_mut("RememberMe")
+        .and_then(|section| section.remove("Data"))
+    else {
+        return Ok(());
+    };
+
+    // Which account the token belongs to is only known once it signs in
+    save_secret(LEGACY_TOKEN_KEY, &legacy_token)?;
     write_ini_file(&config_path, &ini_content)?;
 
+    println!("Moved the saved login to the credential store");
     Ok(())
 }
 
+/// Signs in to the most recently used remembered account, if remember me is on
 pub async fn fetch_saved_user_login() -> Result<AccountInfo, ConfigError> {
+    // Startup already tried, this only gets further when something changed since
+    if let Err(e) = migrate_plaintext_credentials().await {
+        eprintln!("Couldn't move the saved logins to the credential store: {}", e);
+    }
+
     let config_path = get_game_user_config_path()?;
 
     if !config_path.exists() {
@@ -121,18 +168,31 @@ pub async fn fetch_saved_user_login() -> Result<AccountInfo, ConfigError> {
 
     let ini_content = parse_ini_file(&config_path)?;
 
-    if ini_content.contains_key("RememberMe")
-        && ini_content["RememberMe"].contains_key("Enabled")
-        && ini_content["RememberMe"]["Enabled"].eq_ignore_ascii_case("true")
-        && ini_content["RememberMe"].contains_key("Data")
-    {
-        let account_info: AccountInfo =
-            login_user_refresh(&ini_content["RememberMe"]["Data"]).await?;
-        save_user_login(true, account_info.refresh_token.clone()).await?;
-        return Ok(account_info);
-    } else {
+    let remember_me = ini_content
+        .get("RememberMe")
+        .and_then(|section| section.get("Enabled"))
+        .is_some_and(|value| value.eq_ignore_ascii_case("true"));
+    if !remember_me {
         return Err(ConfigError::MissingConfigSection);
     }
+
+    if let Some(legacy_token) = load_secret(LEGACY_TOKEN_KEY)? {
+        let account_info: AccountInfo = login_user_refresh(&legacy_token).await?;
+        save_user_login(true, &account_info).await?;
+        delete_secret(LEGACY_TOKEN_KEY)?;
+        return Ok(account_info);
+    }
+
+    let last_used = get_remembered_accounts()
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(ConfigError::MissingConfigSection)?;
+
+    let refresh_token = get_remembered_token(&last_used.account_id).await?;
+    let account_info: AccountInfo = login_user_refresh(&refresh_token).await?;
+    remember_account(&account_info).await?;
+    Ok(account_info)
 }
 
 pub async fn get_remember_me_value() -> Result<bool, ConfigError> {
@@ -153,4 +213,70 @@ pub async fn get_remember_me_value() -> Result<bool, ConfigError> {
     }
 
     Ok(false)
-}
\ No newline at end of file
+}
+
+/// Reads which file hashes downloads and verifies have to match, falling back to the strongest one
+pub fn get_hash_policy() -> HashPolicy {
+    get_game_user_config_path()
+        .and_then(|path| parse_ini_file(&path))
+        .ok()
+        .and_then(|ini| ini.get("Verification")?.get("HashPolicy").cloned())
+        .and_then(|value| HashPolicy::parse(&value))
+        .unwrap_or_default()
+}
+
+pub async fn save_hash_policy(policy: HashPolicy) -> Result<(), ConfigError> {
+    let config_path = get_game_user_config_path()?;
+
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut ini_content = if config_path.exists() {
+        parse_ini_file(&config_path)?
+    } else {
+        HashMap::new()
+    };
+
+    ini_content
+        .entry("Verification".to_string())
+        .or_insert_with(HashMap::new)
+        .insert("HashPolicy".to_string(), policy.as_str().to_string());
+
+    write_ini_file(&config_path, &ini_content)?;
+
+    Ok(())
+}
+
+/// Whether identical files of different installs are hard linked to one copy in the shared store. Off unless switched on.
+pub fn get_shared_store_enabled() -> bool {
+    get_game_user_config_path()
+        .and_then(|path| parse_ini_file(&path))
+        .ok()
+        .and_then(|ini| ini.get("Storage")?.get("SharedStore").cloned())
+        .map(|value| value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+pub async fn save_shared_store_enabled(enabled: bool) -> Result<(), ConfigError> {
+    let config_path = get_game_user_config_path()?;
+
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut ini_content = if config_path.exists() {
+        parse_ini_file(&config_path)?
+    } else {
+        HashMap::new()
+    };
+
+    ini_content
+        .entry("Storage".to_string())
+        .or_insert_with(HashMap::new)
+        .insert("SharedStore".to_string(), enabled.to_string());
+
+    write_ini_file(&config_path, &ini_content)?;
+
+    Ok(())
+}
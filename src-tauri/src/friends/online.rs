@@ -3,23 +3,19 @@ use reqwest::Client;
 use crate::auth::{AccountInfo, AuthError, Services};
 use crate::auth::responses::ErrorResponse;
 
-use crate::cache::get_account_info;
+use crate::auth::session::{current_account, send_authorized};
 use crate::friends::errors::FriendError;
 
 use crate::friends::responses::{DisplayNameLookupResponse, SummaryResponse};
 
 /// Utility function to retrieve the friend summary
 async fn get_friend_summary() -> Result<SummaryResponse, FriendError> {
-    let account_info: &AccountInfo = get_account_info().await?;
+    let account_info: AccountInfo = current_account().await?;
 
     let client = Client::new();
     let url = format!("{0}/friends/api/v1/{1}/summary", Services::FRIENDS, account_info.account_id);
 
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let response = send_authorized(|| client.get(&url)).await?;
 
     if response.status() != 200 {
         if let Ok(error_response) = response.json::<ErrorResponse>().await {
@@ -37,16 +33,12 @@ async fn get_friend_summary() -> Result<SummaryResponse, FriendError> {
 
 /// Accepts or sends a friend request by friend account id
 pub async fn accept_friend(friend_account_id: String) -> Result<(), FriendError> {
-    let account_info: &AccountInfo = get_account_info().await?;
+    let account_info: AccountInfo = current_account().await?;
 
     let client = Client::new();
     let url = format!("{0}/friends/api/v1/{1}/friends/{2}", Services::FRIENDS, account_info.account_id, friend_account_id);
 
-    let response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let response = send_authorized(|| client.post(&url)).await?;
 
     if response.status() != 204 {
         if let Ok(error_response) = response.json::<ErrorResponse>().await {
@@ -63,16 +55,12 @@ pub async fn accept_friend(friend_account_id: String) -> Result<(), FriendError>
 
 /// Declines a friend request by friend account id
 pub async fn decline_friend(friend_account_id: String) -> Result<(), FriendError> {
-    let account_info: &AccountInfo = get_account_info().await?;
+    let account_info: AccountInfo = current_account().await?;
 
     let client = Client::new();
     let url = format!("{0}/friends/api/v1/{1}/friends/{2}", Services::FRIENDS, account_info.account_id, friend_account_id);
 
-    let response = client
-        .delete(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let response = send_authorized(|| client.delete(&url)).await?;
 
     if response.status() != 204 {
         if let Ok(error_response) = response.json::<ErrorResponse>().await {
@@ -89,16 +77,10 @@ pub async fn decline_friend(friend_account_id: String) -> Result<(), FriendError
 
 /// Gets the account display name from an account id
 pub async fn get_display_name_by_account_id(account_id: String) -> Result<String, FriendError> {
-    let account_info: &AccountInfo = get_account_info().await?;
-
     let client = Client::new();
     let url = format!("{0}/account/api/public/account/{1}", Services::ACCOUNT, account_id);
 
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let response = send_authorized(|| client.get(&url)).await?;
 
     if response.status() != 200 {
         if let Ok(error_response) = response.json::<ErrorResponse>().await {
@@ -1,5 +1,6 @@
 use thiserror::Error;
 
+use crate::auth::AuthError;
 use crate::config::ConfigError;
 
 #[derive(Error, Debug)]
@@ -13,6 +14,9 @@ pub enum GameInfoError {
     #[error("JSON parsing error: {0}")]
     JsonError(#[from] serde_json::Error),
 
+    #[error("{0}")]
+    AuthError(#[from] AuthError),
+
     #[error("Config Error: {0}")]
     ConfigError(#[from] ConfigError),
 
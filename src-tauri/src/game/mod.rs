@@ -4,23 +4,18 @@ pub mod responses;
 use errors::GameInfoError;
 use reqwest::Client;
 
-use crate::auth::{AccountInfo, ErrorResponse, Services};
-use crate::cache::get_account_info;
+use crate::auth::session::send_authorized;
+use crate::auth::{ErrorResponse, Services};
+use crate::config::install_state::refresh_update_available;
 use crate::config::installed::get_object_by_artifact_id;
 use crate::game::responses::{CatalogResponse, GameInfo};
 use crate::manifest::downloader::get_build_version;
 
 pub async fn fetch_current_game_data() -> Result<GameInfo, GameInfoError> {
-    let account_info: &AccountInfo = get_account_info().await?;
-
     let client = Client::new();
     let url = format!("{0}/catalog/api/shared/bulk/items?id={1}", Services::CATALOG, Services::CATALOG_ID);
 
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let response = send_authorized(|| client.get(&url)).await?;
 
     if !response.status().is_success() {
         if let Ok(error_response) = response.json::<ErrorResponse>().await {
@@ -34,9 +29,22 @@ pub async fn fetch_current_game_data() -> Result<GameInfo, GameInfoError> {
 
     let catalog_response: CatalogResponse = response.json().await?;
 
-    let build_version: String = match get_object_by_artifact_id(Services::CATALOG_ID).await {
-        Ok(installed_game) => installed_game.app_version,
-        Err(_) => get_build_version().await.map_err(|_| GameInfoError::UnexpectedError)?
+    // The game page shows the default install, the others are listed with `list_installs`
+    let default_install = get_object_by_artifact_id(Services::CATALOG_ID).await.ok();
+    let recorded_state = default_install.as_ref().map(|installed| installed.state).unwrap_or_default();
+
+    let (build_version, state) = match default_install.filter(|installed| installed.state.is_installed()) {
+        Some(installed_game) => {
+            let state = match get_build_version().await {
+                Ok(live_version) => refresh_update_available(&installed_game, &live_version).await.unwrap_or(installed_game.state),
+                Err(_) => installed_game.state,
+            };
+            (installed_game.app_version, state)
+        }
+        None => (
+            get_build_version().await.map_err(|_| GameInfoError::UnexpectedError)?,
+            recorded_state,
+        ),
     };
 
     Ok(GameInfo {
@@ -46,7 +54,8 @@ pub async fn fetch_current_game_data() -> Result<GameInfo, GameInfoError> {
             .find(|img| img.image_type == "Offer")
             .map_or("".to_string(), |img| img.url.clone()),
         badge: "Early Access".to_string(),
-        installed: false,
+        installed: state.is_installed(),
+        state,
         description: catalog_response[Services::CATALOG_ID].description.clone(),
         version: build_version,
         screenshots: catalog_response[Services::CATALOG_ID].key_images.iter()
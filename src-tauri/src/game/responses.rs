@@ -2,6 +2,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::config::install_state::InstallState;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameInfo {
     pub id: String,
@@ -9,6 +11,7 @@ pub struct GameInfo {
     pub image: String,
     pub badge: String,
     pub installed: bool,
+    pub state: InstallState,
     pub description: String,
     pub version: String,
     pub screenshots: Vec<String>
@@ -31,6 +31,9 @@ pub enum LaunchError {
     #[error("Dll not loaded")]
     DllNotLoaded,
 
+    #[error("Can't start the game while {0} is in progress")]
+    OperationInProgress(String),
+
     #[error("Launch Failed {0}")]
     LaunchFailed(String),
 
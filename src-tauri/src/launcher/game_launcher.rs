@@ -11,10 +11,14 @@ use winapi::um::winnt::{PROCESS_QUERY_INFORMATION, PROCESS_TERMINATE};
 use winapi::um::handleapi::CloseHandle;
 #[cfg(target_os = "windows")]
 use winapi::um::tlhelp32::{CreateToolhelp32Snapshot, Process32First, Process32Next, PROCESSENTRY32, TH32CS_SNAPPROCESS};
+#[cfg(target_os = "windows")]
+use sysinfo::{Pid, ProcessExt, System, SystemExt};
 
 use crate::launcher::errors::LaunchError;
 use crate::launcher::anticheat::download_anticheat;
 use crate::launcher::launcher::download_launcher;
+#[cfg(target_os = "windows")]
+use crate::operations::operation_info::{is_within, normalize_install_dir};
 
 /// Configuration for game launch parameters
 #[derive(Debug, Clone)]
@@ -45,6 +49,28 @@ impl ProcessUtils {
         Self::is_process_running("FortniteClient-Win64-Shipping.exe")
     }
 
+    /// Check if Fortnite is running from the given install, rather than from any install
+    pub fn is_game_running_from(install_dir: &str) -> bool {
+        let mut system = System::new();
+        system.refresh_processes();
+        !Self::processes_from(&system, install_dir, &["FortniteClient-Win64-Shipping.exe"]).is_empty()
+    }
+
+    /// Ids of the named processes started from inside an install. One whose path can't be read counts as inside, so
+    /// a job leaves the files alone rather than risk changing them under the game.
+    fn processes_from(system: &System, install_dir: &str, process_names: &[&str]) -> Vec<Pid> {
+        let install_dir = normalize_install_dir(install_dir);
+        process_names
+            .iter()
+            .flat_map(|name| system.processes_by_exact_name(name))
+            .filter(|process| {
+                let exe = process.exe().to_string_lossy();
+                exe.is_empty() || is_within(&normalize_install_dir(&exe), &install_dir)
+            })
+            .map(|process| process.pid())
+            .collect()
+    }
+
     /// Check if a specific process is running by name
     pub fn is_process_running(process_name: &str) -> bool {
         unsafe {
@@ -102,6 +128,27 @@ impl ProcessUtils {
         Ok(())
     }
 
+    /// Close the game and its anti-cheat started from one install, leaving copies started from other installs running
+    pub fn close_game_from(install_dir: &str) -> Result<(), LaunchError> {
+        let mut system = System::new();
+        system.refresh_processes();
+
+        let processes = Self::processes_from(
+            &system,
+            install_dir,
+            &["FortniteClient-Win64-Shipping_EAC.exe", "FortniteClient-Win64-Shipping.exe"],
+        );
+        for pid in processes {
+            if let Some(process) = system.process(pid) {
+                process.kill();
+            }
+        }
+
+        // Wait a moment for processes to fully terminate
+        thread::sleep(Duration::from_secs(1));
+        Ok(())
+    }
+
     /// Kill a process by name
     pub fn kill_process_by_name(process_name: &str) -> Result<(), LaunchError> {
         unsafe {
@@ -165,6 +212,10 @@ impl ProcessUtils {
         false // Stub for non-Windows
     }
 
+    pub fn is_game_running_from(_install_dir: &str) -> bool {
+        false // Stub for non-Windows
+    }
+
     pub fn is_process_running(_process_name: &str) -> bool {
         false // Stub for non-Windows
     }
@@ -173,6 +224,10 @@ impl ProcessUtils {
         Ok(()) // Stub for non-Windows
     }
 
+    pub fn close_game_from(_install_dir: &str) -> Result<(), LaunchError> {
+        Ok(()) // Stub for non-Windows
+    }
+
     pub fn kill_process_by_name(_process_name: &str) -> Result<(), LaunchError> {
         Ok(()) // Stub for non-Windows
     }
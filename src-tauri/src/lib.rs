@@ -7,15 +7,34 @@ pub mod friends;
 pub mod game;
 pub mod manifest;
 pub mod launcher;
+pub mod operations;
+pub mod retry;
 
+use auth::session::keep_session_fresh;
 use commands::*;
-
-use std::sync::Arc;
+use config::migrate_plaintext_credentials;
+use config::install_state::attach_app_handle;
+use operations::get_operation_manager;
+use operations::recovery::recover_interrupted_operations;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
-        .manage(Arc::new(DownloadControl::default()))
+        .manage(get_operation_manager().clone())
+        .setup(|app| {
+            attach_app_handle(app.handle().clone());
+
+            // Cleans up after a crash before anything can start a new operation
+            tauri::async_runtime::block_on(recover_interrupted_operations());
+
+            // Gets saved logins out of plain text before anything signs in with them
+            if let Err(e) = tauri::async_runtime::block_on(migrate_plaintext_credentials()) {
+                eprintln!("Couldn't move the saved logins to the credential store: {}", e);
+            }
+
+            tauri::async_runtime::spawn(keep_session_fresh());
+            Ok(())
+        })
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
@@ -29,6 +48,10 @@ pub fn run() {
             fetchsu,
             fetchcu,
             fetchcc,
+            logout,
+            switch_account,
+            list_accounts,
+            forget_remembered_account,
             get_progress,
             cancel_download,
             resume_download,
@@ -37,10 +60,26 @@ pub fn run() {
             message_box_okay,
             uninstall_complete,
             start_verify,
+            get_verify_report,
+            get_orphan_files,
+            delete_orphan_files,
+            get_verification_hash_policy,
+            set_verification_hash_policy,
+            get_storage_report,
+            clean_manifest_cache,
+            clean_temp_files,
+            clean_shared_store,
+            get_shared_store,
+            set_shared_store,
             start_uninstall,
+            preview_uninstall,
+            move_install,
+            import_install,
+            preflight_install_location,
             fetch_installed_object_by_artifact_id,
             update_installed_object_by_artifact_id,
             push_installed_object,
+            list_installs,
             get_drives,
             launch_game,
             get_is_playing,
@@ -51,7 +90,16 @@ pub fn run() {
             fetch_display_name_by_account_id,
             accept_friend_request,
             decline_friend_request,
-            get_game_information
+            get_game_information,
+            list_operations,
+            get_operation_history,
+            cancel_operation,
+            reprioritize_operation,
+            get_interrupted_operations,
+            get_game_install_state,
+            dismiss_interrupted_operation,
+            rollback_to_version,
+            get_cached_build_versions
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -15,7 +15,13 @@ use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_s3::Client;
 use aws_sdk_s3::Config;
 
-use reality_lib::commands::{start_download_internal, start_verify_internal, DownloadControl};
+use reality_lib::commands::{
+    start_download_internal, start_verify_internal, verify_report_internal, DownloadControl,
+};
+use reality_lib::manifest::downloader::integrity::VerifyMode;
+use reality_lib::manifest::downloader::orphans::remove_orphan_files;
+use reality_lib::manifest::parse_manifest;
+use reality_lib::retry::{circuit_breaker_for, RetryPolicy};
 
 #[derive(Parser)]
 #[command(name = "reality-manifest")]
@@ -89,6 +95,18 @@ enum Commands {
         /// Manifest file
         #[arg(short, long)]
         manifest: String,
+
+        /// Re-hash every file instead of only the ones that changed since the last verify
+        #[arg(long)]
+        full: bool,
+
+        /// Only check the install and write a JSON report to this path instead of repairing
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        /// Delete files the manifest doesn't reference, except user data and launcher files
+        #[arg(long)]
+        remove_orphans: bool,
     },
 }
 
@@ -99,35 +117,24 @@ async fn upload_single_file_with_retry(
     key: &str,
     max_retries: usize,
 ) -> Result<()> {
-    let mut attempt = 0;
-    let base_delay_ms = 250; // Start with 250ms
-    let max_delay_ms = 30_000; // Cap at 30 seconds
-    
-    loop {
-        attempt += 1;
-        
-        match upload_single_file(client, bucket, path, key).await {
-            Ok(_) => {
-                if attempt > 1 {
-                    println!("Successfully uploaded {} on attempt {}", path.display(), attempt);
-                } else {
-                    println!("Uploaded {}", path.display());
-                }
-                return Ok(());
-            }
-            Err(e) => {
-                if attempt > max_retries {
-                    eprintln!("Failed to upload {} after {} attempts: {}", path.display(), max_retries, e);
-                    return Err(e);
-                }
-                
-                // Exponential backoff: 250ms, 500ms, 1s, 2s, 4s, etc. (capped at 30s)
-                let delay_ms = std::cmp::min(base_delay_ms * (1 << (attempt - 1)), max_delay_ms);
-                eprintln!("Upload attempt {} failed for {}: {}. Retrying in {}ms...", 
-                         attempt, path.display(), e, delay_ms);
-                
-                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
-            }
+    let policy = RetryPolicy {
+        max_retries: max_retries as u32,
+        ..RetryPolicy::UPLOAD
+    };
+    let breaker = circuit_breaker_for(bucket);
+    let label = format!("Upload of {}", path.display());
+
+    match policy
+        .run(&label, Some(&breaker), || upload_single_file(client, bucket, path, key))
+        .await
+    {
+        Ok(()) => {
+            println!("Uploaded {}", path.display());
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Failed to upload {}: {}", path.display(), e);
+            Err(e)
         }
     }
 }
@@ -352,13 +359,43 @@ async fn main() -> Result<()> {
             install_dir,
             bucket,
             manifest,
+            full,
+            report,
+            remove_orphans,
         }) => {
             let file_bytes = fs::read(manifest)?;
             let manifest_b64 = BASE64_STANDARD.encode(&file_bytes);
 
             let control = Arc::new(DownloadControl::default());
 
-            start_verify_internal(manifest_b64, bucket, install_dir, control).await?;
+            let mode = if full { VerifyMode::Full } else { VerifyMode::Quick };
+
+            match report {
+                Some(report_path) => {
+                    let report = verify_report_internal(manifest_b64, install_dir, control, mode).await?;
+                    fs::write(&report_path, serde_json::to_string_pretty(&report)?)?;
+
+                    println!(
+                        "{} of {} files valid, {} missing, {} corrupt, {} wrong size, {} extra. Report written to {}",
+                        report.valid_files,
+                        report.checked_files,
+                        report.missing.len(),
+                        report.corrupt.len(),
+                        report.size_mismatched.len(),
+                        report.extra.len(),
+                        report_path.display()
+                    );
+                }
+                None => {
+                    start_verify_internal(manifest_b64, bucket, install_dir.clone(), control, mode).await?;
+
+                    if remove_orphans {
+                        let parsed = parse_manifest(file_bytes).await?;
+                        let freed = remove_orphan_files(&PathBuf::from(&install_dir), &parsed, None).await?;
+                        println!("Removed orphaned files, freed {} bytes", freed);
+                    }
+                }
+            }
         }
 
         None => {
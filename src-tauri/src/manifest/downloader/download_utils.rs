@@ -2,10 +2,11 @@ use aws_config::Region;
 use aws_credential_types::Credentials;
 use aws_sdk_s3::{Client, Config};
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout};
 
-use crate::manifest::chunk_data::load_chunk;
+use crate::manifest::chunk_data::{load_chunk, ChunkInfo};
 use crate::manifest::errors::ChunkLoadError;
+use crate::retry::{circuit_breaker_for, RetryPolicy, Retryable};
 
 pub fn hardcoded_s3_client() -> Client {
     let creds = Credentials::new(
@@ -33,32 +34,12 @@ pub fn hardcoded_s3_client() -> Client {
 }
 
 pub async fn download_chunk_from_r2(bucket: &str, key: &str) -> Result<Vec<u8>, ChunkLoadError> {
-    const MAX_RETRIES: u32 = 3;
-    const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
-
-    let mut attempt = 0;
-
-    loop {
-        match download_chunk_attempt(bucket, key).await {
-            Ok(data) => return Ok(data),
-            Err(e) => {
-                attempt += 1;
-
-                if attempt > MAX_RETRIES {
-                    return Err(e);
-                }
-
-                // Exponential backoff
-                let backoff = INITIAL_BACKOFF * (2_u32.pow(attempt - 1));
-                eprintln!(
-                    "Download attempt {} failed for {}: {}. Retrying in {:?}...",
-                    attempt, key, e, backoff
-                );
-
-                sleep(backoff).await;
-            }
-        }
-    }
+    let breaker = circuit_breaker_for(bucket);
+    RetryPolicy::CHUNK
+        .run(&format!("Download of {}", key), Some(&breaker), || {
+            download_chunk_attempt(bucket, key)
+        })
+        .await
 }
 
 async fn download_chunk_attempt(bucket: &str, key: &str) -> Result<Vec<u8>, ChunkLoadError> {
@@ -79,113 +60,130 @@ async fn download_chunk_attempt(bucket: &str, key: &str) -> Result<Vec<u8>, Chun
     load_chunk(&raw.into_bytes())
 }
 
-// Alternative approach with manual streaming for better control
-pub async fn download_chunk_from_r2_streaming(
-    bucket: &str,
-    key: &str,
-) -> Result<Vec<u8>, ChunkLoadError> {
-    const MAX_RETRIES: u32 = 3;
-    const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Where objects are downloaded from. Anything that looks like a URL is fetched over plain HTTP, everything else is an R2 bucket.
+pub enum ObjectSource<'a> {
+    Bucket(&'a str),
+    Http(&'a str),
+}
 
-    let mut attempt = 0;
+impl<'a> ObjectSource<'a> {
+    pub fn parse(bucket: &'a str) -> Self {
+        if bucket.starts_with("http://") || bucket.starts_with("https://") {
+            ObjectSource::Http(bucket.trim_end_matches('/'))
+        } else {
+            ObjectSource::Bucket(bucket)
+        }
+    }
+}
 
-    loop {
-        match download_chunk_streaming_attempt(bucket, key).await {
-            Ok(data) => return Ok(data),
-            Err(e) => {
-                attempt += 1;
+/// Bytes of an object received so far. Kept across attempts so a stalled transfer resumes where it left off.
+#[derive(Default)]
+pub struct PartialTransfer {
+    pub data: Vec<u8>,
+    pub total_size: Option<u64>,
+}
 
-                if attempt > MAX_RETRIES {
-                    return Err(e);
-                }
+impl PartialTransfer {
+    fn is_complete(&self) -> bool {
+        self.total_size
+            .is_some_and(|total| self.data.len() as u64 >= total)
+    }
 
-                let backoff = INITIAL_BACKOFF * (2_u32.pow(attempt - 1));
-                eprintln!(
-                    "Streaming download attempt {} failed for {}: {}. Retrying in {:?}...",
-                    attempt, key, e, backoff
-                );
+    fn reset(&mut self) {
+        self.data.clear();
+        self.total_size = None;
+    }
 
-                sleep(backoff).await;
+    /// Starts over if the server answered with the whole object instead of the range we asked for
+    fn begin_response(&mut self, content_range: Option<&str>, content_length: Option<u64>) {
+        match content_range.and_then(parse_content_range_total) {
+            Some(total) => self.total_size = Some(total),
+            None => {
+                self.data.clear();
+                self.total_size = content_length;
             }
         }
+
+        if let Some(total) = self.total_size {
+            self.data.reserve((total as usize).saturating_sub(self.data.len()));
+        }
     }
 }
 
-async fn download_chunk_streaming_attempt(
+/// Reads the total size out of a `Content-Range: bytes start-end/total` header
+fn parse_content_range_total(content_range: &str) -> Option<u64> {
+    content_range.rsplit('/').next()?.trim().parse().ok()
+}
+
+// Downloads and validates a chunk, resuming partial transfers with ranged requests
+pub async fn download_chunk_from_r2_streaming(
     bucket: &str,
     key: &str,
 ) -> Result<Vec<u8>, ChunkLoadError> {
-    use tokio::time::timeout;
-
-    let resp = hardcoded_s3_client()
-        .get_object()
-        .bucket(bucket)
-        .key(key)
-        .send()
-        .await
-        .map_err(|e| {
-            ChunkLoadError::DownloadFailed(key.to_string(), format!("Request failed: {}", e))
-        })?;
-
-    let content_length = resp.content_length().unwrap_or(0) as usize;
-    let mut buffer = Vec::with_capacity(content_length);
-
-    let mut stream = resp.body.into_async_read();
-    let mut chunk_buffer = [0u8; 8192]; // 8KB chunks
-
-    loop {
-        match timeout(
-            Duration::from_secs(10),
-            tokio::io::AsyncReadExt::read(&mut stream, &mut chunk_buffer),
+    // Transfer failures are already retried by the streaming download, only a corrupt result is fetched again here
+    RetryPolicy::CHUNK
+        .run_if(
+            &format!("Validation of chunk {}", key),
+            None,
+            || async {
+                // Chunks are only validated once every byte has arrived
+                let data = download_from_bucket_streaming(bucket, key).await?;
+                load_chunk(&data)
+            },
+            |e| {
+                matches!(
+                    e,
+                    ChunkLoadError::IncorrectFileSize
+                        | ChunkLoadError::DecompressFailure
+                        | ChunkLoadError::HashCheckFailed
+                )
+            },
         )
         .await
-        {
-            Ok(Ok(0)) => break, // EOF
-            Ok(Ok(n)) => {
-                buffer.extend_from_slice(&chunk_buffer[..n]);
-            }
-            Ok(Err(e)) => {
-                return Err(ChunkLoadError::DownloadFailed(
-                    key.to_string(),
-                    format!("Read error: {}", e),
-                ));
-            }
-            Err(_) => {
-                return Err(ChunkLoadError::DownloadFailed(
-                    key.to_string(),
-                    "Read timeout".to_string(),
-                ));
-            }
-        }
-    }
-
-    load_chunk(&buffer)
 }
 
-// Manual streaming to download a file from the bucket
+// Manual streaming to download a file from the bucket, resuming with ranged requests after stalls
 pub async fn download_from_bucket_streaming(
     bucket: &str,
     key: &str,
 ) -> Result<Vec<u8>, ChunkLoadError> {
-    const MAX_RETRIES: u32 = 3;
-    const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
-
-    let mut attempt = 0;
+    let policy = RetryPolicy::CHUNK;
+    let breaker = circuit_breaker_for(bucket);
+    let source = ObjectSource::parse(bucket);
+    let mut transfer = PartialTransfer::default();
+    let mut retry = 0;
 
     loop {
-        match download_from_bucket_streaming_attempt(bucket, key).await {
-            Ok(data) => return Ok(data),
+        breaker.wait_until_closed().await?;
+        let received_before = transfer.data.len();
+
+        match download_range_attempt(&source, key, &mut transfer).await {
+            Ok(()) => {
+                breaker.record_success();
+                return Ok(transfer.data);
+            }
+            Err(e) if !e.is_retryable() => return Err(e),
             Err(e) => {
-                attempt += 1;
+                // Attempts that made progress don't count, so slow but working connections still finish
+                if transfer.data.len() > received_before {
+                    breaker.record_success();
+                    retry = 0;
+                } else {
+                    breaker.record_failure();
+                    retry += 1;
+                }
 
-                if attempt > MAX_RETRIES {
+                if retry > policy.max_retries {
                     return Err(e);
                 }
 
-                let backoff = INITIAL_BACKOFF * (2_u32.pow(attempt - 1));
+                let backoff = policy.backoff(retry.max(1));
                 eprintln!(
-                    "Streaming download attempt {} failed for {}: {}. Retrying in {:?}...",
-                    attempt, key, e, backoff
+                    "Streaming download of {} stalled at {} bytes: {}. Resuming in {:?}...",
+                    key,
+                    transfer.data.len(),
+                    e,
+                    backoff
                 );
 
                 sleep(backoff).await;
@@ -194,57 +192,155 @@ pub async fn download_from_bucket_streaming(
     }
 }
 
-async fn download_from_bucket_streaming_attempt(
-    bucket: &str,
+async fn download_range_attempt(
+    source: &ObjectSource<'_>,
     key: &str,
-) -> Result<Vec<u8>, ChunkLoadError> {
-    use tokio::time::timeout;
-
-    let resp = hardcoded_s3_client()
-        .get_object()
-        .bucket(bucket)
-        .key(key)
-        .send()
-        .await
-        .map_err(|e| {
-            ChunkLoadError::DownloadFailed(key.to_string(), format!("Request failed: {}", e))
-        })?;
+    transfer: &mut PartialTransfer,
+) -> Result<(), ChunkLoadError> {
+    if transfer.is_complete() {
+        return Ok(());
+    }
 
-    let content_length = resp.content_length().unwrap_or(0) as usize;
-    let mut buffer = Vec::with_capacity(content_length);
+    let offset = transfer.data.len();
+    let range = format!("bytes={}-", offset);
 
-    let mut stream = resp.body.into_async_read();
-    let mut chunk_buffer = [0u8; 8192]; // 8KB chunks
+    match source {
+        ObjectSource::Bucket(bucket) => {
+            let mut request = hardcoded_s3_client().get_object().bucket(*bucket).key(key);
+            if offset > 0 {
+                request = request.range(range);
+            }
 
-    loop {
-        match timeout(
-            Duration::from_secs(10),
-            tokio::io::AsyncReadExt::read(&mut stream, &mut chunk_buffer),
-        )
-        .await
-        {
-            Ok(Ok(0)) => break, // EOF
-            Ok(Ok(n)) => {
-                buffer.extend_from_slice(&chunk_buffer[..n]);
+            let resp = request.send().await.map_err(|e| {
+                ChunkLoadError::DownloadFailed(key.to_string(), format!("Request failed: {}", e))
+            })?;
+
+            transfer.begin_response(resp.content_range(), resp.content_length().map(|l| l as u64));
+
+            let mut stream = resp.body.into_async_read();
+            let mut chunk_buffer = [0u8; 8192]; // 8KB chunks
+
+            loop {
+                match timeout(
+                    Duration::from_secs(10),
+                    tokio::io::AsyncReadExt::read(&mut stream, &mut chunk_buffer),
+                )
+                .await
+                {
+                    Ok(Ok(0)) => break, // EOF
+                    Ok(Ok(n)) => {
+                        transfer.data.extend_from_slice(&chunk_buffer[..n]);
+                    }
+                    Ok(Err(e)) => {
+                        return Err(ChunkLoadError::DownloadFailed(
+                            key.to_string(),
+                            format!("Read error: {}", e),
+                        ));
+                    }
+                    Err(_) => {
+                        return Err(ChunkLoadError::DownloadFailed(
+                            key.to_string(),
+                            "Read timeout".to_string(),
+                        ));
+                    }
+                }
             }
-            Ok(Err(e)) => {
-                return Err(ChunkLoadError::DownloadFailed(
-                    key.to_string(),
-                    format!("Read error: {}", e),
-                ));
+        }
+        ObjectSource::Http(base_url) => {
+            let url = format!("{}/{}", base_url, key);
+            let mut request = reqwest::Client::new().get(&url);
+            if offset > 0 {
+                request = request.header(reqwest::header::RANGE, range);
             }
-            Err(_) => {
+
+            let mut resp = request.send().await.map_err(|e| {
+                ChunkLoadError::DownloadFailed(key.to_string(), format!("Request failed: {}", e))
+            })?;
+
+            if !resp.status().is_success() {
+                if resp.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                    transfer.reset();
+                }
                 return Err(ChunkLoadError::DownloadFailed(
                     key.to_string(),
-                    "Read timeout".to_string(),
+                    format!("Request failed: {}", resp.status()),
                 ));
             }
+
+            let content_range = match resp.status() {
+                reqwest::StatusCode::PARTIAL_CONTENT => resp
+                    .headers()
+                    .get(reqwest::header::CONTENT_RANGE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string()),
+                _ => None,
+            };
+            transfer.begin_response(content_range.as_deref(), resp.content_length());
+
+            loop {
+                match timeout(Duration::from_secs(10), resp.chunk()).await {
+                    Ok(Ok(Some(bytes))) => transfer.data.extend_from_slice(&bytes),
+                    Ok(Ok(None)) => break, // EOF
+                    Ok(Err(e)) => {
+                        return Err(ChunkLoadError::DownloadFailed(
+                            key.to_string(),
+                            format!("Read error: {}", e),
+                        ));
+                    }
+                    Err(_) => {
+                        return Err(ChunkLoadError::DownloadFailed(
+                            key.to_string(),
+                            "Read timeout".to_string(),
+                        ));
+                    }
+                }
+            }
         }
     }
 
-    Ok(buffer)
+    match transfer.total_size {
+        Some(total) if (transfer.data.len() as u64) < total => Err(ChunkLoadError::DownloadFailed(
+            key.to_string(),
+            format!("Connection closed after {} of {} bytes", transfer.data.len(), total),
+        )),
+        _ => Ok(()),
+    }
 }
 
 pub fn guid_to_u128(guid: &[u32; 4]) -> u128 {
     (guid[0] as u128) << 96 | (guid[1] as u128) << 64 | (guid[2] as u128) << 32 | (guid[3] as u128)
 }
+
+/// Bucket key a chunk is stored under
+pub fn chunk_key(chunk: &ChunkInfo) -> String {
+    format!(
+        "ChunksV4/{:02}/{:016X}_{}.chunk",
+        chunk.group_num,
+        chunk.hash,
+        chunk
+            .guid
+            .iter()
+            .map(|g| format!("{:08X}", g))
+            .collect::<String>()
+    )
+}
+
+/// Checks whether an object is still available in the bucket without downloading it
+pub async fn object_exists(bucket: &str, key: &str) -> Result<bool, ChunkLoadError> {
+    match hardcoded_s3_client()
+        .head_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(e) => match e.as_service_error() {
+            Some(service_error) if service_error.is_not_found() => Ok(false),
+            _ => Err(ChunkLoadError::DownloadFailed(
+                key.to_string(),
+                format!("Request failed: {}", e),
+            )),
+        },
+    }
+}
@@ -1,20 +1,34 @@
 
-use sha1::{Digest, Sha1};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use std::{collections::HashMap, path::PathBuf};
 use tokio::fs::{self, File};
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::io::AsyncWriteExt;
 use tokio::sync::{mpsc, Semaphore, Mutex};
 
 use crate::manifest::chunk_data::ChunkInfo;
-use crate::manifest::downloader::download_utils::{download_chunk_from_r2_streaming, guid_to_u128};
+use crate::manifest::downloader::download_utils::{chunk_key, download_chunk_from_r2_streaming, guid_to_u128};
 use crate::manifest::downloader::errors::DownloadError;
+use crate::config::get_hash_policy;
+use crate::manifest::downloader::hashing::{file_matches, HashPolicy};
+use crate::manifest::downloader::integrity::{hash_parts, IntegrityDb};
+use crate::manifest::downloader::orphans::remove_dropped_files;
 use crate::manifest::downloader::progress_update::ProgressUpdate;
+use crate::manifest::downloader::reuse::{other_install_sources, read_range, ReuseIndex};
+use crate::manifest::downloader::transaction::{commit_staged_files, discard_staged_files, tmp_path};
+use crate::manifest::errors::ChunkLoadError;
 use crate::manifest::ParsedManifest;
+use crate::retry::{circuit_breaker_for, RetryPolicy, Retryable};
 use crate::DownloadControl;
 
+/// A file that matches its manifest hash, either already in place or waiting at its temp path
+struct BuiltFile {
+    filename: String,
+    staged: bool,
+    part_hashes: Vec<String>,
+}
+
 pub async fn download_game(
     manifest: ParsedManifest,
     bucket: String,
@@ -33,30 +47,18 @@ pub async fn download_game(
             .collect(),
     );
 
-    // Build reuse map from old manifest (filename + chunk guid/offset/size) -> (source filename, absolute byte offset)
-    let mut reuse_map: HashMap<(String, u128, u32, u32), (String, u64)> = HashMap::new();
-    if let Some(old) = &old_manifest {
-        for old_file in &old.file_manifest_list.elements {
-            let mut old_offset = 0u64;
-            for old_cp in &old_file.chunk_parts {
-                let key = (
-                    old_file.filename.clone(),
-                    guid_to_u128(&old_cp.guid),
-                    old_cp.offset,
-                    old_cp.size,
-                );
-                reuse_map.insert(
-                    key,
-                    (
-                        old_file.filename.clone(),
-                        old_offset + (old_cp.offset as u64),
-                    ),
-                );
-                old_offset += old_cp.size as u64;
-            }
-        }
+    // Index every chunk range of the old install so parts can be copied from any file that already holds them.
+    // Old files are only replaced at commit time, after every file has been built, so they stay readable throughout.
+    let mut reuse_index = match &old_manifest {
+        Some(old) => ReuseIndex::from_manifest(old),
+        None => ReuseIndex::default(),
+    };
+
+    // Builds installed side by side share most of their chunks
+    for (other_dir, other_manifest) in other_install_sources(&install_dir).await {
+        reuse_index.add_install(other_dir, &other_manifest);
     }
-    let reuse_map = Arc::new(reuse_map);
+    let reuse_index = Arc::new(reuse_index);
 
     // === Global byte-progress accounting ===
     let total_files = manifest.file_manifest_list.elements.len();
@@ -68,6 +70,11 @@ pub async fn download_game(
         .sum();
     let downloaded_bytes = Arc::new(Mutex::new(0u64));
 
+    let hash_policy = get_hash_policy();
+
+    // A previous outage shouldn't stop this download before it has tried the source
+    circuit_breaker_for(&bucket).reset();
+
     // Limit concurrency
     let semaphore = Arc::new(Semaphore::new(10));
     let mut task_handles = Vec::new();
@@ -79,9 +86,9 @@ pub async fn download_game(
 
         let permit = semaphore.clone().acquire_owned().await.unwrap();
         let final_path = install_dir.join(&fm.filename);
-        let tmp_path = install_dir.join(format!("{}.tmp", &fm.filename));
+        let tmp_path = tmp_path(&install_dir, &fm.filename);
         let chunk_map = chunk_map.clone();
-        let reuse_map = reuse_map.clone();
+        let reuse_index = reuse_index.clone();
         let tx = tx.clone();
         let control = control.clone();
         let bucket = bucket.clone();
@@ -92,12 +99,11 @@ pub async fn download_game(
             let _permit = permit; // keep the semaphore slot until task completes
 
             // Retry logic for the entire file download
-            const MAX_FILE_RETRIES: u32 = 3;
+            let policy = RetryPolicy::FILE;
             let mut file_attempt = 0;
 
             loop {
                 if control.cancelled.load(Ordering::Relaxed) {
-                    // Clean up partial file on cancellation
                     let _ = fs::remove_file(&tmp_path).await;
                     return Err(DownloadError::Cancelled);
                 }
@@ -107,7 +113,9 @@ pub async fn download_game(
                     &final_path,
                     &tmp_path,
                     &chunk_map,
-                    &reuse_map,
+                    &reuse_index,
+                    // Reused bytes may be what broke the last attempt, so retries download everything
+                    file_attempt == 0,
                     &tx,
                     &control,
                     &bucket,
@@ -115,9 +123,17 @@ pub async fn download_game(
                     total_files,
                     total_bytes,
                     downloaded_bytes.clone(),
+                    hash_policy,
                 )
                 .await {
-                    Ok(()) => break,
+                    // Staged files are only moved into place once every file is ready
+                    Ok((staged, part_hashes)) => {
+                        return Ok(BuiltFile {
+                            filename: fm.filename.clone(),
+                            staged,
+                            part_hashes,
+                        })
+                    }
                     Err(DownloadError::Cancelled) => {
                         let _ = fs::remove_file(&tmp_path).await;
                         return Err(DownloadError::Cancelled);
@@ -125,21 +141,21 @@ pub async fn download_game(
                     Err(e) => {
                         file_attempt += 1;
 
-                        if file_attempt > MAX_FILE_RETRIES {
+                        if !e.is_retryable() || file_attempt > policy.max_retries {
                             let _ = fs::remove_file(&tmp_path).await;
                             return Err(e);
                         }
 
+                        let retry_delay = policy.backoff(file_attempt);
                         eprintln!(
-                            "File download attempt {} failed for {}: {}. Retrying...",
-                            file_attempt, fm.filename, e
+                            "File download attempt {} failed for {}: {}. Retrying in {:?}...",
+                            file_attempt, fm.filename, e, retry_delay
                         );
 
                         // Clean up partial file
                         let _ = fs::remove_file(&tmp_path).await;
 
                         // Backoff with frequent cancellation checks
-                        let retry_delay = Duration::from_millis(1000 * file_attempt as u64);
                         let mut elapsed = Duration::ZERO;
                         while elapsed < retry_delay {
                             if control.cancelled.load(Ordering::Relaxed) {
@@ -152,48 +168,85 @@ pub async fn download_game(
                     }
                 }
             }
-
-            Ok::<(), DownloadError>(())
         });
 
         task_handles.push(handle);
     }
 
     // Await tasks with cooperative cancellation
+    let mut staged: Vec<String> = Vec::new();
+    let mut part_hashes: HashMap<String, Vec<String>> = HashMap::new();
     let mut results = Vec::new();
     let mut cancelled = false;
 
     for handle in task_handles {
-        if control.cancelled.load(Ordering::Relaxed) {
+        // Wait for aborted tasks to stop, so none is still writing while its staged file gets discarded
+        if cancelled || control.cancelled.load(Ordering::Relaxed) {
             handle.abort();
+            let _ = handle.await;
             cancelled = true;
             continue;
         }
 
         match handle.await {
-            Ok(Ok(())) => results.push(Ok(())),
-            Ok(Err(DownloadError::Cancelled)) => {
-                cancelled = true;
-                break;
+            Ok(Ok(built)) => {
+                if built.staged {
+                    staged.push(built.filename.clone());
+                }
+                part_hashes.insert(built.filename, built.part_hashes);
+                results.push(Ok(()));
             }
+            Ok(Err(DownloadError::Cancelled)) => cancelled = true,
             Ok(Err(e)) => results.push(Err(e)),
-            Err(_) => {
-                // Aborted/panic -> treat as cancelled
-                cancelled = true;
-                break;
-            }
+            // Aborted/panic -> treat as cancelled
+            Err(_) => cancelled = true,
         }
     }
 
+    // Nothing has been moved into place yet, so dropping the staged files leaves the previous build untouched
+    let filenames: Vec<&String> = manifest.file_manifest_list.elements.iter().map(|fm| &fm.filename).collect();
     if cancelled {
+        discard_staged_files(&install_dir, filenames).await;
         return Err(DownloadError::Cancelled);
     }
 
     let failed_files: Vec<DownloadError> = results.into_iter().filter_map(|r| r.err()).collect();
     if !failed_files.is_empty() {
+        discard_staged_files(&install_dir, filenames).await;
+
+        // One outage error says more than thousands of files failing the same way
+        if let Some(outage) = failed_files.iter().find_map(|e| match e {
+            DownloadError::SourceUnavailable(outage) => Some(*outage),
+            _ => None,
+        }) {
+            return Err(DownloadError::SourceUnavailable(outage));
+        }
+
         return Err(DownloadError::Multiple(failed_files));
     }
 
+    // Every file verified, swap them all in at once
+    commit_staged_files(&install_dir, &staged).await?;
+
+    // Every file was just hashed, so the next quick verify can skip all of them
+    let hashed: Vec<(&String, &Vec<u8>, Vec<String>)> = manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .map(|fm| {
+            let parts = part_hashes.remove(&fm.filename).unwrap_or_default();
+            (&fm.filename, &fm.hash, parts)
+        })
+        .collect();
+    IntegrityDb::record_install(&install_dir, hashed).await;
+
+    // Files the new build no longer ships would otherwise pile up in the install directory
+    if let Some(old) = &old_manifest {
+        if let Err(e) = remove_dropped_files(&install_dir, old, &manifest).await {
+            eprintln!("Couldn't remove files dropped by the update: {}", e);
+        }
+    }
+
     // Final 100% progress tick to ensure UI flips to complete
     {
         let mut g = downloaded_bytes.lock().await;
@@ -213,12 +266,14 @@ pub async fn download_game(
     Ok(())
 }
 
+/// Builds a file into its temp path. Returns whether the temp file still has to be moved into place, and the hash of each chunk part.
 async fn download_file_attempt(
     fm: &crate::manifest::file_manifest::FileManifest,
     final_path: &PathBuf,
     tmp_path: &PathBuf,
     chunk_map: &Arc<HashMap<u128, ChunkInfo>>,
-    reuse_map: &Arc<HashMap<(String, u128, u32, u32), (String, u64)>>,
+    reuse_index: &Arc<ReuseIndex>,
+    allow_reuse: bool,
     tx: &mpsc::Sender<ProgressUpdate>,
     control: &Arc<DownloadControl>,
     bucket: &str,
@@ -226,7 +281,8 @@ async fn download_file_attempt(
     total_files: usize,
     total_bytes: u64,
     downloaded_bytes: Arc<Mutex<u64>>,
-) -> Result<(), DownloadError> {
+    hash_policy: HashPolicy,
+) -> Result<(bool, Vec<String>), DownloadError> {
     // Early cancellation
     if control.cancelled.load(Ordering::Relaxed) {
         return Err(DownloadError::Cancelled);
@@ -235,10 +291,7 @@ async fn download_file_attempt(
     // Fast path: existing file matches expected hash -> count its bytes toward global progress
     if final_path.exists() {
         if let Ok(existing) = fs::read(&final_path).await {
-            let mut hasher = Sha1::new();
-            hasher.update(&existing);
-            let hash = hasher.finalize();
-            if hash.as_slice() == fm.hash.as_slice() {
+            if file_matches(fm, &existing, hash_policy) {
                 {
                     let mut global = downloaded_bytes.lock().await;
                     *global += fm.file_size as u64;
@@ -251,7 +304,7 @@ async fn download_file_attempt(
                         })
                         .await;
                 }
-                return Ok(());
+                return Ok((false, hash_parts(fm, &existing)));
             }
         }
     }
@@ -268,18 +321,15 @@ async fn download_file_attempt(
         }
 
         let guid = guid_to_u128(&cp.guid);
-        let key = (fm.filename.clone(), guid, cp.offset, cp.size);
-
-        // Reuse bytes from existing file (from old manifest mapping)
-        if let Some((source_file, source_offset)) = reuse_map.get(&key) {
-            let source_path = install_dir_clone.join(source_file);
-            if let Ok(mut existing_file) = File::open(&source_path).await {
-                let mut buffer = vec![0u8; cp.size as usize];
-                existing_file
-                    .seek(std::io::SeekFrom::Start(*source_offset))
-                    .await?;
-                existing_file.read_exact(&mut buffer).await?;
 
+        // Reuse bytes from whichever file of the old install, or of another install, holds this chunk range
+        let reused = match allow_reuse {
+            true => reuse_index.find(&fm.filename, guid, cp.offset, cp.size),
+            false => None,
+        };
+        if let Some(range) = reused {
+            let source_path = range.path(install_dir_clone);
+            if let Ok(buffer) = read_range(&source_path, range.offset, cp.size).await {
                 file.write_all(&buffer).await?;
 
                 // Global progress update
@@ -302,25 +352,16 @@ async fn download_file_attempt(
 
         // Download the chunk, then slice the part we need
         let chunk = chunk_map.get(&guid).ok_or(DownloadError::ChunkMissing)?;
-        let chunk_key = format!(
-            "ChunksV4/{:02}/{:016X}_{}.chunk",
-            chunk.group_num,
-            chunk.hash,
-            chunk
-                .guid
-                .iter()
-                .map(|g| format!("{:08X}", g))
-                .collect::<String>()
-        );
-
-        let chunk_data = download_chunk_with_cancellation(bucket, &chunk_key, control.clone()).await?;
+        let key = chunk_key(chunk);
+
+        let chunk_data = download_chunk_with_cancellation(bucket, &key, control.clone()).await?;
 
         let start = cp.offset as usize;
         let end = start + cp.size as usize;
         if end > chunk_data.len() {
             return Err(DownloadError::ChunkCorrupt(format!(
                 "Chunk part out of bounds: {} (chunk len = {}, start = {}, end = {})",
-                chunk_key,
+                key,
                 chunk_data.len(),
                 start,
                 end
@@ -355,15 +396,11 @@ async fn download_file_attempt(
 
     // Verify final file hash
     let data = fs::read(&tmp_path).await?;
-    let mut hasher = Sha1::new();
-    hasher.update(&data);
-    let hash = hasher.finalize();
-    if hash.as_slice() != fm.hash.as_slice() {
+    if !file_matches(fm, &data, hash_policy) {
         return Err(DownloadError::HashMismatch(fm.filename.clone()));
     }
 
-    fs::rename(&tmp_path, &final_path).await?;
-    Ok(())
+    Ok((true, hash_parts(fm, &data)))
 }
 
 
@@ -373,8 +410,8 @@ async fn download_chunk_with_cancellation(
     chunk_key: &str,
     control: Arc<DownloadControl>,
 ) -> Result<Vec<u8>, DownloadError> {
-    // Set a reasonable timeout for chunk downloads (e.g., 30 seconds)
-    const CHUNK_TIMEOUT: Duration = Duration::from_secs(30);
+    // No overall timeout: stalled reads are caught and resumed by the streaming download itself,
+    // and a large chunk on a slow connection should be allowed to finish
     
     // Check if cancelled before starting
     if control.cancelled.load(Ordering::Relaxed) {
@@ -391,13 +428,13 @@ async fn download_chunk_with_cancellation(
         }
     };
 
-    // Race the download against the cancellation checker and timeout
+    // Race the download against the cancellation checker
     tokio::select! {
-        result = tokio::time::timeout(CHUNK_TIMEOUT, download_chunk_from_r2_streaming(bucket, chunk_key)) => {
-            match result {
-                Ok(chunk_result) => chunk_result.map_err(|_| DownloadError::UnexpectedError),
-                Err(_) => Err(DownloadError::Timeout),
-            }
+        result = download_chunk_from_r2_streaming(bucket, chunk_key) => {
+            result.map_err(|e| match e {
+                ChunkLoadError::SourceUnavailable(outage) => DownloadError::SourceUnavailable(outage),
+                e => DownloadError::ChunkDownloadFailed(chunk_key.to_string(), e.to_string()),
+            })
         }
         cancelled = cancellation_checker => {
             cancelled
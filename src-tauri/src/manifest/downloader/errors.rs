@@ -5,6 +5,7 @@ use thiserror::Error;
 use crate::manifest::{
     downloader::progress_update::ProgressUpdate, errors::ChunkLoadError, ManifestError,
 };
+use crate::retry::{CircuitOpen, Retryable};
 
 #[derive(Error, Debug)]
 pub enum DownloadError {
@@ -26,6 +27,36 @@ pub enum DownloadError {
     #[error("Downloading chunk {0} failed: {1}")]
     ChunkDownloadFailed(String, String),
 
+    #[error("Update could not be applied and was rolled back: {0}")]
+    RolledBack(String),
+
+    #[error("{0} chunks needed for this build are no longer available")]
+    ChunksUnavailable(usize),
+
+    #[error("{0}")]
+    SourceUnavailable(#[from] CircuitOpen),
+
+    #[error("Refusing to uninstall from {0}, it is not a game folder")]
+    UnsafeUninstallPath(String),
+
+    #[error("Fortnite is running and using the game files, close it first")]
+    GameRunning,
+
+    #[error("Can't install to this folder: {0}")]
+    PreflightFailed(String),
+
+    #[error("No game build found in {0}")]
+    NoBuildDetected(String),
+
+    #[error("Cannot move the install there: {0}")]
+    InvalidMoveTarget(String),
+
+    #[error("Not enough free space: {0} bytes needed but only {1} available")]
+    InsufficientSpace(u64, u64),
+
+    #[error("Copy of {0} does not match the original")]
+    CopyMismatch(String),
+
     #[error("Repair failed: {0}")]
     RepairFailed(String),
 
@@ -77,3 +108,19 @@ impl serde::Serialize for DownloadError {
         serializer.serialize_str(&self.to_string())
     }
 }
+
+impl Retryable for DownloadError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            DownloadError::Timeout
+            | DownloadError::HashMismatch(_)
+            | DownloadError::ChunkDownloadFailed(_, _)
+            | DownloadError::ChunkCorrupt(_)
+            | DownloadError::Io(_)
+            | DownloadError::IoError(_)
+            | DownloadError::ByteStreamError(_) => true,
+            DownloadError::ChunkLoadError(e) => e.is_retryable(),
+            _ => false,
+        }
+    }
+}
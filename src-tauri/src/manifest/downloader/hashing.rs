@@ -0,0 +1,131 @@
+use std::io::{self, Read};
+use std::path::Path;
+
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::manifest::file_manifest::FileManifest;
+
+/// Which of the hashes in a file's manifest entry have to match
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashPolicy {
+    /// Only the strongest hash the manifest provides, SHA256 over SHA1 over MD5
+    #[default]
+    Strongest,
+    /// Every hash the manifest provides
+    All,
+}
+
+impl HashPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "strongest" => Some(HashPolicy::Strongest),
+            "all" => Some(HashPolicy::All),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashPolicy::Strongest => "Strongest",
+            HashPolicy::All => "All",
+        }
+    }
+}
+
+/// Unset hashes are written as zeros in some manifests
+fn provided(hash: Option<&Vec<u8>>) -> Option<&[u8]> {
+    hash.filter(|h| h.iter().any(|b| *b != 0)).map(|h| h.as_slice())
+}
+
+/// Hashes file contents with every algorithm the policy asks for
+pub struct FileHasher<'a> {
+    fm: &'a FileManifest,
+    sha256: Option<Sha256>,
+    sha1: Option<Sha1>,
+    md5: Option<Md5>,
+}
+
+impl<'a> FileHasher<'a> {
+    pub fn new(fm: &'a FileManifest, policy: HashPolicy) -> Self {
+        let sha256 = provided(fm.hash_sha256.as_ref()).is_some();
+        let sha1 = provided(Some(&fm.hash)).is_some();
+        let md5 = provided(fm.hash_md5.as_ref()).is_some();
+
+        let (sha256, sha1, md5) = match policy {
+            HashPolicy::All => (sha256, sha1, md5),
+            HashPolicy::Strongest => (sha256, sha1 && !sha256, md5 && !sha256 && !sha1),
+        };
+
+        Self {
+            fm,
+            sha256: sha256.then(Sha256::new),
+            sha1: sha1.then(Sha1::new),
+            md5: md5.then(Md5::new),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        if let Some(hasher) = &mut self.sha256 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.sha1 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.md5 {
+            hasher.update(data);
+        }
+    }
+
+    /// Whether every checked hash matched. A file without any usable hash never matches.
+    pub fn matches(self) -> bool {
+        let mut checked = false;
+
+        if let (Some(hasher), Some(expected)) = (self.sha256, provided(self.fm.hash_sha256.as_ref())) {
+            if hasher.finalize().as_slice() != expected {
+                return false;
+            }
+            checked = true;
+        }
+        if let (Some(hasher), Some(expected)) = (self.sha1, provided(Some(&self.fm.hash))) {
+            if hasher.finalize().as_slice() != expected {
+                return false;
+            }
+            checked = true;
+        }
+        if let (Some(hasher), Some(expected)) = (self.md5, provided(self.fm.hash_md5.as_ref())) {
+            if hasher.finalize().as_slice() != expected {
+                return false;
+            }
+            checked = true;
+        }
+
+        checked
+    }
+}
+
+/// Checks file contents held in memory against the manifest
+pub fn file_matches(fm: &FileManifest, data: &[u8], policy: HashPolicy) -> bool {
+    let mut hasher = FileHasher::new(fm, policy);
+    hasher.update(data);
+    hasher.matches()
+}
+
+/// Checks a file on disk against the manifest without reading it into memory all at once
+pub fn file_on_disk_matches(fm: &FileManifest, path: &Path, policy: HashPolicy) -> io::Result<bool> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = FileHasher::new(fm, policy);
+    let mut buffer = vec![0u8; 1024 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.matches())
+}
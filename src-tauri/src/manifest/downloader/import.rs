@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::auth::Services;
+use crate::config::install_state::InstallState;
+use crate::config::installed::{
+    add_or_update_object, get_object_by_artifact_id, get_object_by_location, install_id_for_location, InstalledObject,
+};
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::get_cached_manifests;
+use crate::manifest::downloader::integrity::{IntegrityDb, VerifyMode};
+use crate::manifest::downloader::progress_update::ProgressUpdate;
+use crate::manifest::downloader::report::verify_report;
+use crate::manifest::downloader::verifier::verify_and_repair_parallel;
+use crate::manifest::manifest_data::ManifestMeta;
+use crate::manifest::ParsedManifest;
+use crate::DownloadControl;
+
+/// What importing an existing install found and had to fix
+#[derive(Clone, Debug, Serialize)]
+pub struct ImportReport {
+    pub build_version: String,
+    pub install_dir: String,
+    /// Whether the folder held the build that is currently live, rather than an older cached one
+    pub is_current_build: bool,
+    pub checked_files: usize,
+    pub repaired_files: usize,
+    /// Chunk bytes the repair downloaded
+    pub fetched_bytes: u64,
+}
+
+/// How many of a build's files are in the folder with the right size. Only metadata is read, so this is cheap enough to try every candidate.
+fn match_score(install_dir: &Path, manifest: &ParsedManifest) -> usize {
+    manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .filter(|fm| {
+            std::fs::metadata(install_dir.join(&fm.filename))
+                .map(|m| m.len() == fm.file_size)
+                .unwrap_or(false)
+        })
+        .count()
+}
+
+/// Picks the build the folder most looks like. The current build wins ties, and a folder matching nothing gives nothing.
+pub async fn detect_build(
+    install_dir: &Path,
+    current: Option<ParsedManifest>,
+) -> Result<Option<(ParsedManifest, bool)>, DownloadError> {
+    let mut candidates: Vec<(ParsedManifest, bool)> = Vec::new();
+    let current_version = current.as_ref().map(|manifest| manifest.meta.build_version.clone());
+
+    if let Some(current) = current {
+        candidates.push((current, true));
+    }
+    for (_, manifest) in get_cached_manifests().await? {
+        if Some(&manifest.meta.build_version) != current_version.as_ref() {
+            candidates.push((manifest, false));
+        }
+    }
+
+    let mut best: Option<((ParsedManifest, bool), usize)> = None;
+    for candidate in candidates {
+        let score = match_score(install_dir, &candidate.0);
+        println!("Build {} matches {} files", candidate.0.meta.build_version, score);
+
+        if score > 0 && best.as_ref().is_none_or(|(_, best_score)| score > *best_score) {
+            best = Some((candidate, score));
+        }
+    }
+
+    Ok(best.map(|(candidate, _)| candidate))
+}
+
+/// Adopts a build that is already on disk: finds which build it is, repairs whatever differs from it and registers it as an install
+pub async fn import_install(
+    install_dir: PathBuf,
+    current: Option<ParsedManifest>,
+    bucket: String,
+    tx: mpsc::Sender<ProgressUpdate>,
+    control: Arc<DownloadControl>,
+) -> Result<ImportReport, DownloadError> {
+    if !install_dir.is_dir() {
+        return Err(DownloadError::NoBuildDetected(install_dir.to_string_lossy().to_string()));
+    }
+
+    let Some((manifest, is_current_build)) = detect_build(&install_dir, current).await? else {
+        return Err(DownloadError::NoBuildDetected(install_dir.to_string_lossy().to_string()));
+    };
+    println!("Importing {:?} as build {}", install_dir, manifest.meta.build_version);
+
+    // The files came from somewhere else, so nothing recorded about them can be trusted
+    let report = verify_report(&manifest, install_dir.clone(), VerifyMode::Full, tx.clone(), control.clone()).await?;
+
+    let broken: HashSet<&String> = report
+        .missing
+        .iter()
+        .chain(report.corrupt.iter())
+        .chain(report.size_mismatched.iter().map(|mismatch| &mismatch.filename))
+        .collect();
+
+    // Files that just checked out are remembered, so the repair below doesn't hash them all a second time
+    let mut integrity = IntegrityDb::default();
+    for fm in manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .filter(|fm| !broken.contains(&fm.filename))
+    {
+        if let Err(e) = integrity.record(&install_dir, &fm.filename, &fm.hash, Vec::new()).await {
+            eprintln!("Couldn't record {} in the integrity database: {}", fm.filename, e);
+        }
+    }
+    integrity.save(&install_dir).await?;
+
+    let mut import = ImportReport {
+        build_version: manifest.meta.build_version.clone(),
+        install_dir: install_dir.to_string_lossy().to_string(),
+        is_current_build,
+        checked_files: report.checked_files,
+        repaired_files: broken.len(),
+        fetched_bytes: 0,
+    };
+
+    let meta = manifest.meta.clone();
+    if !report.is_healthy() {
+        println!("Repairing {} files, up to {} bytes to download", broken.len(), report.repair_bytes);
+        let fetched_before = control.fetched_bytes.load(Ordering::Relaxed);
+        verify_and_repair_parallel(manifest, bucket, install_dir.clone(), tx, control.clone(), VerifyMode::Quick).await?;
+        import.fetched_bytes = control.fetched_bytes.load(Ordering::Relaxed) - fetched_before;
+    }
+
+    register_import(&import.install_dir, &meta).await?;
+
+    Ok(import)
+}
+
+/// Records the folder as an install of the build it matched, straight from that build's manifest, so importing works offline
+async fn register_import(install_dir: &str, meta: &ManifestMeta) -> Result<(), DownloadError> {
+    // The state and what's known about the files belong to the install, not to the build
+    let existing = get_object_by_location(install_dir).await.ok();
+
+    // The manifest doesn't carry the catalog ids, so they're kept from this record or taken from another install of the game
+    let known = match existing.as_ref().filter(|object| !object.item_id.is_empty()) {
+        Some(object) => Some(object.clone()),
+        None => get_object_by_artifact_id(Services::CATALOG_ID).await.ok(),
+    };
+
+    let installed = InstalledObject {
+        install_id: existing
+            .as_ref()
+            .map_or_else(|| install_id_for_location(install_dir), |object| object.install_id.clone()),
+        installation_location: install_dir.to_string(),
+        namespace_id: known
+            .as_ref()
+            .map_or_else(|| meta.app_name.clone(), |object| object.namespace_id.clone()),
+        item_id: known.as_ref().map(|object| object.item_id.clone()).unwrap_or_default(),
+        artifact_id: Services::CATALOG_ID.to_string(),
+        app_version: meta.build_version.clone(),
+        app_name: meta.app_name.clone(),
+        rolled_back_from: None,
+        state: existing.as_ref().map_or(InstallState::Installing, |object| object.state),
+        manifest_hash: existing.as_ref().and_then(|object| object.manifest_hash.clone()),
+        install_tags: existing.as_ref().map(|object| object.install_tags.clone()).unwrap_or_default(),
+        last_verified: existing.as_ref().and_then(|object| object.last_verified),
+    };
+
+    add_or_update_object(installed)
+        .await
+        .map_err(|_| DownloadError::UnexpectedError)
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tokio::fs;
+
+use crate::manifest::file_manifest::FileManifest;
+
+/// File inside the install directory remembering what every file looked like when it was last verified
+pub const INTEGRITY_DB_FILE_NAME: &str = ".reality-integrity.json";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerifyMode {
+    /// Only re-hashes files whose size or modification time changed since they were last verified
+    #[default]
+    Quick,
+    /// Re-hashes every file
+    Full,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IntegrityRecord {
+    pub size: u64,
+    pub modified_ns: u64,
+    /// Hex encoded hash the file matched when it was verified
+    pub hash: String,
+    /// SHA1 of each chunk part range, so a damaged file can be repaired part by part
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub part_hashes: Vec<String>,
+}
+
+impl IntegrityRecord {
+    fn same_file(&self, other: &IntegrityRecord) -> bool {
+        self.size == other.size && self.modified_ns == other.modified_ns && self.hash == other.hash
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IntegrityDb {
+    files: HashMap<String, IntegrityRecord>,
+}
+
+fn db_path(install_dir: &Path) -> PathBuf {
+    install_dir.join(INTEGRITY_DB_FILE_NAME)
+}
+
+async fn current_record(path: &Path, hash: &[u8]) -> io::Result<IntegrityRecord> {
+    let metadata = fs::metadata(path).await?;
+    let modified_ns = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+
+    Ok(IntegrityRecord {
+        size: metadata.len(),
+        modified_ns,
+        hash: hex::encode(hash),
+        part_hashes: Vec::new(),
+    })
+}
+
+/// Hashes every chunk part range of a file's contents, in manifest order
+pub fn hash_parts(fm: &FileManifest, data: &[u8]) -> Vec<String> {
+    let mut offset = 0usize;
+
+    fm.chunk_parts
+        .iter()
+        .map(|cp| {
+            let start = offset.min(data.len());
+            let end = (offset + cp.size as usize).min(data.len());
+            offset += cp.size as usize;
+            hex::encode(Sha1::digest(&data[start..end]))
+        })
+        .collect()
+}
+
+impl IntegrityDb {
+    /// Loads the database of an install. A missing or unreadable database just means every file gets hashed.
+    pub async fn load(install_dir: &Path) -> Self {
+        match fs::read(db_path(install_dir)).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                eprintln!("Ignoring unreadable integrity database: {}", e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub async fn save(&self, install_dir: &Path) -> io::Result<()> {
+        let path = db_path(install_dir);
+        let tmp = path.with_extension("json.tmp");
+
+        fs::write(&tmp, serde_json::to_vec(self)?).await?;
+        fs::rename(&tmp, &path).await
+    }
+
+    /// Remembers a file that was just verified against the given hash
+    pub async fn record(
+        &mut self,
+        install_dir: &Path,
+        filename: &str,
+        hash: &[u8],
+        part_hashes: Vec<String>,
+    ) -> io::Result<()> {
+        let mut record = current_record(&install_dir.join(filename), hash).await?;
+        record.part_hashes = part_hashes;
+        self.files.insert(filename.to_string(), record);
+        Ok(())
+    }
+
+    /// Part hashes recorded for a file, if they were taken from the build with the given hash
+    pub fn part_hashes(&self, filename: &str, hash: &[u8]) -> Option<&[String]> {
+        self.files
+            .get(filename)
+            .filter(|record| record.hash == hex::encode(hash) && !record.part_hashes.is_empty())
+            .map(|record| record.part_hashes.as_slice())
+    }
+
+    pub fn forget(&mut self, filename: &str) {
+        self.files.remove(filename);
+    }
+
+    /// Whether a file is still exactly as it was when it last matched the given hash
+    pub async fn is_unchanged(&self, install_dir: &Path, filename: &str, hash: &[u8]) -> bool {
+        let Some(recorded) = self.files.get(filename) else {
+            return false;
+        };
+
+        match current_record(&install_dir.join(filename), hash).await {
+            Ok(current) => current.same_file(recorded),
+            Err(_) => false,
+        }
+    }
+
+    /// Records every file of a freshly installed build, replacing whatever was known about the previous one
+    pub async fn record_install<'a>(
+        install_dir: &Path,
+        files: impl IntoIterator<Item = (&'a String, &'a Vec<u8>, Vec<String>)>,
+    ) {
+        let mut db = Self::default();
+        for (filename, hash, part_hashes) in files {
+            if let Err(e) = db.record(install_dir, filename, hash, part_hashes).await {
+                eprintln!("Couldn't record {} in the integrity database: {}", filename, e);
+            }
+        }
+
+        if let Err(e) = db.save(install_dir).await {
+            eprintln!("Couldn't save the integrity database: {}", e);
+        }
+    }
+}
@@ -1,18 +1,35 @@
 use reqwest::Client;
 
-use crate::cache::get_account_info;
-use crate::config::installed::{add_or_update_object, remove_object_by_artifact_id, InstalledObject};
+use crate::config::install_state::InstallState;
+use crate::config::installed::{
+    add_or_update_object, get_installed_objects, get_object_by_location, install_id_for_location,
+    remove_object_by_install_id, InstalledObject,
+};
 use crate::manifest::downloader::responses::AssetsResponse;
-use crate::manifest::ManifestError;
+use crate::manifest::{parse_manifest, ManifestError, ParsedManifest};
 
 pub mod download_utils;
 pub mod downloader;
 pub mod errors;
+pub mod hashing;
+pub mod import;
+pub mod integrity;
+pub mod orphans;
+pub mod preflight;
 pub mod progress_update;
+pub mod relocate;
+pub mod report;
+pub mod reuse;
+pub mod rollback;
+pub mod storage;
+pub mod store;
+pub mod transaction;
+pub mod uninstall;
 pub mod verifier;
 pub mod responses;
 
-use crate::auth::{AccountInfo, ErrorResponse, Services};
+use crate::auth::session::send_authorized;
+use crate::auth::{ErrorResponse, Services};
 
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
@@ -102,8 +119,52 @@ pub async fn get_second_latest_manifest_b64() -> Result<String, ManifestError> {
     }
 }
 
-/// Marks the game as deleted by removing the manifest folder and the installed object from the launcher installed data.
-pub async fn mark_game_as_deleted() -> Result<(), ManifestError> {
+/// Function that returns every manifest in the cache path along with its parsed contents
+pub async fn get_cached_manifests() -> Result<Vec<(PathBuf, ParsedManifest)>, ManifestError> {
+    let cache_path = get_manifest_cache_path()?;
+    let mut manifests: Vec<(PathBuf, ParsedManifest)> = Vec::new();
+
+    if !cache_path.exists() {
+        return Ok(manifests);
+    }
+
+    for entry in fs::read_dir(&cache_path)? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("manifest") {
+            continue;
+        }
+
+        let data = fs::read(&path)?;
+        match parse_manifest(data).await {
+            Ok(parsed) => manifests.push((path, parsed)),
+            Err(e) => eprintln!("Skipping unreadable cached manifest {:?}: {}", path, e),
+        }
+    }
+
+    Ok(manifests)
+}
+
+/// Function that returns the cached manifest for a build version
+pub async fn find_cached_manifest(build_version: &str) -> Result<(PathBuf, ParsedManifest), ManifestError> {
+    get_cached_manifests()
+        .await?
+        .into_iter()
+        .find(|(_, manifest)| manifest.meta.build_version == build_version)
+        .ok_or_else(|| ManifestError::BuildNotCached(build_version.to_string()))
+}
+
+/// Marks an install as deleted by removing its installed object from the launcher installed data.
+/// The manifest folder is only removed along with the last install, since the others still verify and update from it.
+pub async fn mark_game_as_deleted(install_id: Option<&str>) -> Result<(), ManifestError> {
+    if let Some(install_id) = install_id {
+        // Ignore error, as it might not exist
+        let _ = remove_object_by_install_id(install_id).await;
+    }
+
+    if !get_installed_objects().await.unwrap_or_default().is_empty() {
+        return Ok(());
+    }
+
     let cache_path = get_manifest_cache_path()?;
     if cache_path.exists() {
         fs::remove_dir_all(&cache_path).map_err(|e| {
@@ -111,25 +172,16 @@ pub async fn mark_game_as_deleted() -> Result<(), ManifestError> {
         })?;
     }
 
-    match remove_object_by_artifact_id(Services::CATALOG_ID).await {
-        Ok(_) => Ok(()),
-        Err(_) => Ok(()) // Ignore error, as it might not exist
-    }
+    Ok(())
 }
 
 
 /// Downloads the manifest for the latest version of the game and returns it as a byte vector.
 pub async fn download_manifest() -> Result<Vec<u8>, ManifestError> {
-    let account_info: &AccountInfo = get_account_info().await?;
-
     let client = Client::new();
     let url = format!("{0}/launcher/api/public/assets/Windows/{1}/Fortnite?label={2}", Services::LAUNCHER, Services::CATALOG_ID, Services::CATALOG_LABEL);
 
-    let auth_response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let auth_response = send_authorized(|| client.get(&url)).await?;
 
     if !auth_response.status().is_success() {
         if let Ok(error_response) = auth_response.json::<ErrorResponse>().await {
@@ -149,11 +201,7 @@ pub async fn download_manifest() -> Result<Vec<u8>, ManifestError> {
         assets_response.items["MANIFEST"].path
     );
 
-    let manifest_response = client
-        .get(&manifest_url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let manifest_response = send_authorized(|| client.get(&manifest_url)).await?;
 
     if !manifest_response.status().is_success() {
         if let Ok(error_response) = manifest_response.json::<ErrorResponse>().await {
@@ -206,16 +254,10 @@ pub async fn download_manifest() -> Result<Vec<u8>, ManifestError> {
 /// Completes the manifest download by fetching the current build data and updating the installed object.
 /// This function is called after the manifest has been downloaded and verified.
 pub async fn complete_manifest_download(installation_location: &String) -> Result<(), ManifestError> {
-    let account_info: &AccountInfo = get_account_info().await?;
-
     let client = Client::new();
     let url = format!("{0}/launcher/api/public/assets/Windows/{1}/Fortnite?label={2}", Services::LAUNCHER, Services::CATALOG_ID, Services::CATALOG_LABEL);
 
-    let auth_response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let auth_response = send_authorized(|| client.get(&url)).await?;
 
     if !auth_response.status().is_success() {
         if let Ok(error_response) = auth_response.json::<ErrorResponse>().await {
@@ -229,13 +271,24 @@ pub async fn complete_manifest_download(installation_location: &String) -> Resul
 
     let assets_response: AssetsResponse = auth_response.json().await?;
 
+    // The state and what's known about the files belong to the install, not to the build data
+    let existing = get_object_by_location(installation_location).await.ok();
+
     let current_installed_object : InstalledObject = InstalledObject {
+        install_id: existing
+            .as_ref()
+            .map_or_else(|| install_id_for_location(installation_location), |object| object.install_id.clone()),
         installation_location: installation_location.clone(),
         namespace_id: assets_response.app_name.clone(),
         item_id: assets_response.asset_id.clone(),
         artifact_id: assets_response.catalog_item_id.clone(),
         app_version: assets_response.build_version.clone(),
         app_name: assets_response.app_name.clone(),
+        rolled_back_from: None,
+        state: existing.as_ref().map_or(InstallState::Installing, |object| object.state),
+        manifest_hash: existing.as_ref().and_then(|object| object.manifest_hash.clone()),
+        install_tags: existing.as_ref().map(|object| object.install_tags.clone()).unwrap_or_default(),
+        last_verified: existing.as_ref().and_then(|object| object.last_verified),
     };
 
     add_or_update_object(current_installed_object).await?;
@@ -245,16 +298,10 @@ pub async fn complete_manifest_download(installation_location: &String) -> Resul
 
 /// Gets the build version of the current manifest
 pub async fn get_build_version() -> Result<String, ManifestError> {
-    let account_info: &AccountInfo = get_account_info().await?;
-
     let client = Client::new();
     let url = format!("{0}/launcher/api/public/assets/Windows/{1}/Fortnite?label={2}", Services::LAUNCHER, Services::CATALOG_ID, Services::CATALOG_LABEL);
 
-    let auth_response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", account_info.access_token))
-        .send()
-        .await?;
+    let auth_response = send_authorized(|| client.get(&url)).await?;
 
     if !auth_response.status().is_success() {
         if let Ok(error_response) = auth_response.json::<ErrorResponse>().await {
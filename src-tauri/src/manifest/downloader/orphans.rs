@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+use tokio::fs;
+
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::report::find_extra_files;
+use crate::manifest::downloader::transaction::prune_empty_dirs;
+use crate::manifest::ParsedManifest;
+
+/// Paths under the install root that hold user data or launcher files rather than build files, and are never orphans
+pub const ORPHAN_ALLOWLIST: &[&str] = &[
+    "FortniteGame/Saved/",
+    "FortniteGame/Binaries/Win64/Reality/",
+    "FortniteGame/Binaries/Win64/RealityLauncher.exe",
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OrphanFile {
+    pub filename: String,
+    pub size: u64,
+}
+
+pub fn is_allowlisted(filename: &str) -> bool {
+    let filename = filename.replace('\\', "/").to_lowercase();
+    ORPHAN_ALLOWLIST
+        .iter()
+        .any(|allowed| filename.starts_with(&allowed.to_lowercase()))
+}
+
+/// Files under the install root the manifest doesn't reference, leaving out allowlisted user and launcher data
+pub fn find_orphan_files(install_dir: &Path, manifest: &ParsedManifest) -> io::Result<Vec<OrphanFile>> {
+    Ok(find_extra_files(install_dir, manifest)?
+        .into_iter()
+        .filter(|filename| !is_allowlisted(filename))
+        .map(|filename| {
+            let size = std::fs::metadata(install_dir.join(&filename))
+                .map(|m| m.len())
+                .unwrap_or(0);
+            OrphanFile { filename, size }
+        })
+        .collect())
+}
+
+/// Deletes the given files, then any folders they leave empty. Returns how many bytes were freed.
+pub async fn remove_files(install_dir: &Path, filenames: &[String]) -> Result<u64, DownloadError> {
+    let mut freed = 0u64;
+
+    for filename in filenames {
+        let path = install_dir.join(filename);
+        let size = fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+
+        match fs::remove_file(&path).await {
+            Ok(()) => freed += size,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+
+        prune_empty_dirs(install_dir, &path).await;
+    }
+
+    Ok(freed)
+}
+
+/// Deletes orphaned files. With a selection, only the selected files that really are orphans are deleted.
+pub async fn remove_orphan_files(
+    install_dir: &Path,
+    manifest: &ParsedManifest,
+    selection: Option<Vec<String>>,
+) -> Result<u64, DownloadError> {
+    let mut orphans: Vec<String> = find_orphan_files(install_dir, manifest)?
+        .into_iter()
+        .map(|orphan| orphan.filename)
+        .collect();
+
+    if let Some(selection) = selection {
+        let selection: HashSet<String> = selection.iter().map(|f| f.replace('\\', "/")).collect();
+        orphans.retain(|filename| selection.contains(filename));
+    }
+
+    println!("Removing {} orphaned files", orphans.len());
+    remove_files(install_dir, &orphans).await
+}
+
+/// Deletes the files an update dropped from the manifest
+pub async fn remove_dropped_files(
+    install_dir: &Path,
+    old_manifest: &ParsedManifest,
+    new_manifest: &ParsedManifest,
+) -> Result<u64, DownloadError> {
+    let kept: HashSet<String> = new_manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .map(|fm| fm.filename.to_lowercase())
+        .collect();
+
+    let dropped: Vec<String> = old_manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .filter(|fm| !kept.contains(&fm.filename.to_lowercase()) && !is_allowlisted(&fm.filename))
+        .map(|fm| fm.filename.clone())
+        .collect();
+
+    if !dropped.is_empty() {
+        println!("Removing {} files dropped by the update", dropped.len());
+    }
+
+    remove_files(install_dir, &dropped).await
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::auth::Services;
+use crate::config::drives::disk_for_path;
+use crate::config::installed::get_installed_objects;
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::orphans::is_allowlisted;
+use crate::manifest::downloader::report::list_install_files;
+use crate::manifest::ParsedManifest;
+use crate::operations::operation_info::{is_within, normalize_install_dir};
+
+/// Longest path Windows APIs accept unless long path support is switched on, which the game can't rely on
+const MAX_PATH: usize = 260;
+
+/// Something about an install location that stops an install, or that the user should know about first
+#[derive(Error, Clone, Debug, Serialize)]
+#[serde(tag = "kind")]
+pub enum PreflightIssue {
+    #[error("Not enough free space: {needed} bytes needed but only {available} available")]
+    InsufficientSpace { needed: u64, available: u64 },
+
+    #[error("The folder can't be written to")]
+    ReadOnly,
+
+    #[error("The folder is on a network drive, which makes the game slow to load")]
+    NetworkDrive,
+
+    #[error("Some game files would have a path of {longest} characters, over the limit of {limit}")]
+    PathTooLong { longest: usize, limit: usize },
+
+    #[error("The folder already holds {files} files that aren't part of the game")]
+    NotEmpty { files: usize },
+
+    #[error("The folder overlaps another install at {location}")]
+    UsedByAnotherInstall { location: String },
+}
+
+/// Whether a folder is fit to install a build into. Errors stop the install, warnings are only shown.
+#[derive(Clone, Debug, Serialize)]
+pub struct PreflightReport {
+    pub path: String,
+    /// Bytes the build still has to write here, counting files that are already in place
+    pub required_bytes: u64,
+    pub available_bytes: Option<u64>,
+    pub errors: Vec<PreflightIssue>,
+    pub warnings: Vec<PreflightIssue>,
+}
+
+impl PreflightReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Bytes the build writes into the folder. New files are built next to the ones they replace, so those old copies keep taking space until the install commits.
+/// A file already there with the right size is assumed unchanged, since hashing the whole install just to plan is too slow.
+fn required_bytes(path: &Path, manifest: &ParsedManifest) -> u64 {
+    manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .filter(|fm| {
+            std::fs::metadata(path.join(&fm.filename))
+                .map(|m| m.len() != fm.file_size)
+                .unwrap_or(true)
+        })
+        .map(|fm| fm.file_size)
+        .sum()
+}
+
+/// The folder itself, or the closest parent of it that exists yet
+fn nearest_existing(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|dir| dir.is_dir())
+}
+
+fn is_writable(path: &Path) -> bool {
+    let Some(dir) = nearest_existing(path) else {
+        return false;
+    };
+
+    // The folder may not exist yet, in which case whether it can be created is what counts
+    let probe = dir.join(".reality-preflight");
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// UNC paths are always remote. Mapped network drives don't show up as local disks, which gives them away too.
+fn is_network_path(path: &Path, on_local_disk: bool) -> bool {
+    let path = path.to_string_lossy();
+    path.starts_with(r"\\") || path.starts_with("//") || !on_local_disk
+}
+
+fn longest_path(path: &Path, manifest: &ParsedManifest) -> usize {
+    manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .map(|fm| path.join(format!("{}.tmp", fm.filename)).as_os_str().len())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Files already in the folder that neither this build nor the launcher put there
+fn foreign_files(path: &Path, manifest: &ParsedManifest) -> usize {
+    if !path.is_dir() {
+        return 0;
+    }
+
+    let build_files: HashSet<String> = manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .map(|fm| fm.filename.replace('\\', "/").to_lowercase())
+        .collect();
+
+    list_install_files(path)
+        .map(|files| {
+            files
+                .iter()
+                .filter(|file| !build_files.contains(&file.to_lowercase()) && !is_allowlisted(file))
+                .filter(|file| !file.to_lowercase().ends_with(".tmp"))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// Installs registered anywhere else that sit inside the folder, or that the folder would sit inside
+async fn overlapping_installs(path: &Path, artifact_id: &str) -> Vec<String> {
+    let target = normalize_install_dir(&path.to_string_lossy());
+
+    get_installed_objects()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|installed| {
+            let location = normalize_install_dir(&installed.installation_location);
+            match installed.artifact_id == artifact_id && location == target {
+                // Installing over itself is just an update
+                true => false,
+                false => is_within(&target, &location) || is_within(&location, &target),
+            }
+        })
+        .map(|installed| installed.installation_location)
+        .collect()
+}
+
+/// Checks a folder before installing a build into it
+pub async fn preflight_install_location(
+    path: &Path,
+    manifest: &ParsedManifest,
+) -> PreflightReport {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    let required = required_bytes(path, manifest);
+    let disk = disk_for_path(path);
+    let available = disk.as_ref().map(|(_, available)| *available);
+    if let Some(available) = available {
+        if available < required {
+            errors.push(PreflightIssue::InsufficientSpace {
+                needed: required,
+                available,
+            });
+        }
+    }
+
+    if !is_writable(path) {
+        errors.push(PreflightIssue::ReadOnly);
+    }
+
+    let longest = longest_path(path, manifest);
+    if longest >= MAX_PATH {
+        errors.push(PreflightIssue::PathTooLong {
+            longest,
+            limit: MAX_PATH - 1,
+        });
+    }
+
+    for location in overlapping_installs(path, Services::CATALOG_ID).await {
+        errors.push(PreflightIssue::UsedByAnotherInstall { location });
+    }
+
+    if is_network_path(path, disk.is_some()) {
+        warnings.push(PreflightIssue::NetworkDrive);
+    }
+
+    let foreign = foreign_files(path, manifest);
+    if foreign > 0 {
+        warnings.push(PreflightIssue::NotEmpty { files: foreign });
+    }
+
+    PreflightReport {
+        path: path.to_string_lossy().to_string(),
+        required_bytes: required,
+        available_bytes: available,
+        errors,
+        warnings,
+    }
+}
+
+/// Runs the checks and turns any error into a failed operation
+pub async fn ensure_install_location(
+    path: &Path,
+    manifest: &ParsedManifest,
+) -> Result<PreflightReport, DownloadError> {
+    let report = preflight_install_location(path, manifest).await;
+    if report.is_ok() {
+        return Ok(report);
+    }
+
+    let reasons: Vec<String> = report.errors.iter().map(|issue| issue.to_string()).collect();
+    Err(DownloadError::PreflightFailed(reasons.join(", ")))
+}
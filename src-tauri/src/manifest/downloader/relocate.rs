@@ -0,0 +1,317 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use sha1::{Digest, Sha1};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+use crate::config::drives::disk_for_path;
+use crate::config::installed::{get_installed_objects, get_object_by_location, update_object_by_install_id};
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::orphans::{is_allowlisted, remove_files};
+use crate::manifest::downloader::progress_update::ProgressUpdate;
+use crate::manifest::downloader::transaction::prune_empty_dirs;
+use crate::manifest::downloader::uninstall::plan_uninstall;
+use crate::operations::operation_info::{is_within, normalize_install_dir};
+use crate::DownloadControl;
+
+const COPY_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Refuses relative targets and targets inside the install, or containing it
+fn check_move_target(source: &Path, target: &Path) -> Result<(), DownloadError> {
+    let invalid = |reason: &str| DownloadError::InvalidMoveTarget(format!("{} ({})", target.display(), reason));
+
+    if !target.is_absolute() || target.parent().is_none() {
+        return Err(invalid("not a folder path"));
+    }
+    if target.components().any(|c| c == Component::ParentDir) {
+        return Err(invalid("contains `..`"));
+    }
+
+    let (source, target) = (normalize_install_dir(&source.to_string_lossy()), normalize_install_dir(&target.to_string_lossy()));
+    if is_within(&target, &source) || is_within(&source, &target) {
+        return Err(invalid("overlaps the current install"));
+    }
+
+    Ok(())
+}
+
+/// Refuses targets that overlap another install, whose files would get mixed up with the moved ones
+async fn check_other_installs(source: &Path, target: &Path) -> Result<(), DownloadError> {
+    let source = normalize_install_dir(&source.to_string_lossy());
+    let normalized_target = normalize_install_dir(&target.to_string_lossy());
+
+    for installed in get_installed_objects().await.unwrap_or_default() {
+        let location = normalize_install_dir(&installed.installation_location);
+        if location != source && (is_within(&normalized_target, &location) || is_within(&location, &normalized_target)) {
+            return Err(DownloadError::InvalidMoveTarget(format!(
+                "{} (overlaps the install at {})",
+                target.display(),
+                installed.installation_location
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// The game and launcher files of the install, plus the user data that belongs with them
+async fn files_to_move(source: &Path) -> Result<Vec<(String, u64)>, DownloadError> {
+    let plan = plan_uninstall(source).await?;
+
+    let mut files: Vec<(String, u64)> = plan.files.into_iter().map(|file| (file.filename, file.size)).collect();
+    for filename in plan.kept.into_iter().filter(|filename| is_allowlisted(filename)) {
+        let size = fs::metadata(source.join(&filename)).await.map(|m| m.len()).unwrap_or(0);
+        files.push((filename, size));
+    }
+
+    Ok(files)
+}
+
+async fn send_progress(
+    tx: &mpsc::Sender<ProgressUpdate>,
+    filename: &str,
+    moved_bytes: u64,
+    total_bytes: u64,
+    total_files: usize,
+) {
+    let _ = tx
+        .send(ProgressUpdate {
+            filename: filename.to_string(),
+            downloaded_bytes: moved_bytes,
+            total_bytes,
+            total_files,
+        })
+        .await;
+}
+
+/// Renames every file across, putting the ones already moved back if one fails
+async fn rename_files(
+    source: &Path,
+    target: &Path,
+    files: &[(String, u64)],
+    tx: &mpsc::Sender<ProgressUpdate>,
+    control: &DownloadControl,
+) -> Result<(), DownloadError> {
+    let total_bytes: u64 = files.iter().map(|(_, size)| size).sum();
+    let mut moved_bytes = 0u64;
+    let mut moved: Vec<&String> = Vec::new();
+
+    let mut result = Ok(());
+    for (filename, size) in files {
+        if control.cancelled.load(Ordering::Relaxed) {
+            result = Err(DownloadError::Cancelled);
+            break;
+        }
+
+        let to = target.join(filename);
+        if let Some(parent) = to.parent() {
+            if let Err(e) = fs::create_dir_all(parent).await {
+                result = Err(e.into());
+                break;
+            }
+        }
+        let from = source.join(filename);
+        if let Err(e) = fs::rename(&from, &to).await {
+            result = Err(e.into());
+            break;
+        }
+        prune_empty_dirs(source, &from).await;
+
+        moved.push(filename);
+        moved_bytes += size;
+        send_progress(tx, filename, moved_bytes, total_bytes, files.len()).await;
+    }
+
+    if result.is_err() {
+        rename_back(source, target, moved).await;
+    }
+
+    result
+}
+
+async fn rename_back<'a>(source: &Path, target: &Path, filenames: impl IntoIterator<Item = &'a String>) {
+    for filename in filenames {
+        let (from, to) = (target.join(filename), source.join(filename));
+        if let Some(parent) = to.parent() {
+            let _ = fs::create_dir_all(parent).await;
+        }
+        if fs::rename(&from, &to).await.is_ok() {
+            prune_empty_dirs(target, &from).await;
+        }
+    }
+}
+
+/// Copies a file in pieces so a cancel takes effect quickly. Returns the SHA1 of what was read.
+async fn copy_file(
+    from: &Path,
+    to: &Path,
+    control: &DownloadControl,
+    mut on_progress: impl FnMut(u64),
+) -> Result<Vec<u8>, DownloadError> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let mut reader = File::open(from).await?;
+    let mut writer = File::create(to).await?;
+    let mut hasher = Sha1::new();
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+
+    loop {
+        if control.cancelled.load(Ordering::Relaxed) {
+            return Err(DownloadError::Cancelled);
+        }
+
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read]).await?;
+        on_progress(read as u64);
+    }
+
+    writer.flush().await?;
+    writer.sync_all().await?;
+
+    Ok(hasher.finalize().to_vec())
+}
+
+async fn hash_file(path: &Path, control: &DownloadControl, mut on_progress: impl FnMut(u64)) -> Result<Vec<u8>, DownloadError> {
+    let mut reader = File::open(path).await?;
+    let mut hasher = Sha1::new();
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+
+    loop {
+        if control.cancelled.load(Ordering::Relaxed) {
+            return Err(DownloadError::Cancelled);
+        }
+
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+        on_progress(read as u64);
+    }
+
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Copies every file across and reads each copy back to check it. The originals are left alone.
+async fn copy_files(
+    source: &Path,
+    target: &Path,
+    files: &[(String, u64)],
+    tx: &mpsc::Sender<ProgressUpdate>,
+    control: &DownloadControl,
+) -> Result<(), DownloadError> {
+    // Every byte is written once and read back once
+    let total_bytes: u64 = files.iter().map(|(_, size)| size).sum::<u64>() * 2;
+    let mut done_bytes = 0u64;
+
+    for (filename, _) in files {
+        let to = target.join(filename);
+
+        // Game files run to several gigabytes, so progress is reported as each piece goes through. A full channel just skips one.
+        let mut advance = |bytes: u64| {
+            done_bytes += bytes;
+            let _ = tx.try_send(ProgressUpdate {
+                filename: filename.clone(),
+                downloaded_bytes: done_bytes,
+                total_bytes,
+                total_files: files.len(),
+            });
+        };
+
+        let copied = copy_file(&source.join(filename), &to, control, &mut advance).await?;
+        let written = hash_file(&to, control, &mut advance).await?;
+        if written != copied {
+            return Err(DownloadError::CopyMismatch(filename.clone()));
+        }
+    }
+
+    send_progress(tx, "Move complete", total_bytes, total_bytes, files.len()).await;
+
+    Ok(())
+}
+
+/// Moves an install to another folder and points the installed game at it.
+/// On the same disk the files are renamed, otherwise they are copied, checked, and only then removed from the old folder.
+pub async fn move_install(
+    source: PathBuf,
+    target: PathBuf,
+    tx: mpsc::Sender<ProgressUpdate>,
+    control: Arc<DownloadControl>,
+) -> Result<(), DownloadError> {
+    check_move_target(&source, &target)?;
+    check_other_installs(&source, &target).await?;
+
+    let files = files_to_move(&source).await?;
+    let total_bytes: u64 = files.iter().map(|(_, size)| size).sum();
+
+    if let Some((filename, _)) = files.iter().find(|(filename, _)| target.join(filename).exists()) {
+        return Err(DownloadError::InvalidMoveTarget(format!(
+            "{} (already contains {})",
+            target.display(),
+            filename
+        )));
+    }
+
+    let source_disk = disk_for_path(&source);
+    let target_disk = disk_for_path(&target);
+    let same_disk = match (&source_disk, &target_disk) {
+        (Some((source_mount, _)), Some((target_mount, _))) => source_mount == target_mount,
+        _ => false,
+    };
+
+    if same_disk {
+        println!("Moving {} files to {:?} by renaming", files.len(), target);
+        rename_files(&source, &target, &files, &tx, &control).await?;
+    } else {
+        if let Some((_, available)) = target_disk {
+            if available < total_bytes {
+                return Err(DownloadError::InsufficientSpace(total_bytes, available));
+            }
+        }
+
+        println!("Copying {} files ({} bytes) to {:?}", files.len(), total_bytes, target);
+        if let Err(e) = copy_files(&source, &target, &files, &tx, &control).await {
+            // Drop the partial copy, the original install is still intact
+            let filenames: Vec<String> = files.iter().map(|(filename, _)| filename.clone()).collect();
+            let _ = remove_files(&target, &filenames).await;
+            return Err(e);
+        }
+    }
+
+    let mut installed = get_object_by_location(&source.to_string_lossy())
+        .await
+        .map_err(|_| DownloadError::UnexpectedError)?;
+    installed.installation_location = target.to_string_lossy().to_string();
+    let filenames: Vec<String> = files.into_iter().map(|(filename, _)| filename).collect();
+
+    if update_object_by_install_id(installed).await.is_err() {
+        // The game would point at a folder it is no longer in, so undo the move
+        match same_disk {
+            true => rename_back(&source, &target, &filenames).await,
+            false => {
+                let _ = remove_files(&target, &filenames).await;
+            }
+        }
+        return Err(DownloadError::UnexpectedError);
+    }
+
+    if !same_disk {
+        remove_files(&source, &filenames).await?;
+    }
+
+    // Gone once everything has left, unless something else lives in it too
+    let _ = fs::remove_dir(&source).await;
+
+    Ok(())
+}
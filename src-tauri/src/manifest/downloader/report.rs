@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::manifest::chunk_data::ChunkInfo;
+use crate::manifest::downloader::download_utils::guid_to_u128;
+use crate::manifest::downloader::errors::DownloadError;
+use crate::config::get_hash_policy;
+use crate::manifest::downloader::hashing::{file_on_disk_matches, HashPolicy};
+use crate::manifest::downloader::integrity::{IntegrityDb, VerifyMode, INTEGRITY_DB_FILE_NAME};
+use crate::manifest::downloader::orphans::find_orphan_files;
+use crate::manifest::downloader::progress_update::ProgressUpdate;
+use crate::manifest::downloader::transaction::STAGING_DIR_NAME;
+use crate::manifest::file_manifest::FileManifest;
+use crate::manifest::ParsedManifest;
+use crate::DownloadControl;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SizeMismatch {
+    pub filename: String,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+/// What a verify found, without anything on disk having been changed
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub build_version: String,
+    pub install_dir: String,
+    pub mode: VerifyMode,
+    pub checked_files: usize,
+    pub valid_files: usize,
+    pub missing: Vec<String>,
+    pub corrupt: Vec<String>,
+    pub size_mismatched: Vec<SizeMismatch>,
+    /// Files in the install directory the manifest doesn't reference, other than user and launcher data
+    pub extra: Vec<String>,
+    /// Compressed chunk bytes a repair would have to download
+    pub repair_bytes: u64,
+}
+
+impl VerifyReport {
+    pub fn is_healthy(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty() && self.size_mismatched.is_empty()
+    }
+}
+
+enum FileState {
+    Valid,
+    Missing,
+    Corrupt,
+    SizeMismatch(u64),
+}
+
+async fn check_file(
+    fm: &FileManifest,
+    install_dir: &Path,
+    integrity: &IntegrityDb,
+    mode: VerifyMode,
+    hash_policy: HashPolicy,
+) -> Result<FileState, DownloadError> {
+    let path = install_dir.join(&fm.filename);
+
+    let metadata = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FileState::Missing),
+        Err(e) => return Err(e.into()),
+    };
+
+    if metadata.len() != fm.file_size {
+        return Ok(FileState::SizeMismatch(metadata.len()));
+    }
+
+    if mode == VerifyMode::Quick && integrity.is_unchanged(install_dir, &fm.filename, &fm.hash).await {
+        return Ok(FileState::Valid);
+    }
+
+    let fm = fm.clone();
+    let valid = tokio::task::spawn_blocking(move || file_on_disk_matches(&fm, &path, hash_policy)).await??;
+    match valid {
+        true => Ok(FileState::Valid),
+        false => Ok(FileState::Corrupt),
+    }
+}
+
+/// Lists every file under a directory as manifest style paths relative to it
+pub fn walk_files(root: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                files.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Lists every file under the install directory, skipping the launcher's own bookkeeping
+pub fn list_install_files(install_dir: &Path) -> io::Result<Vec<String>> {
+    let staging_prefix = format!("{}/", STAGING_DIR_NAME);
+
+    Ok(walk_files(install_dir)?
+        .into_iter()
+        .filter(|file| !file.starts_with(&staging_prefix) && file != INTEGRITY_DB_FILE_NAME)
+        .collect())
+}
+
+/// Files on disk the manifest doesn't reference. Windows paths are case insensitive, so the comparison is too.
+pub fn find_extra_files(install_dir: &Path, manifest: &ParsedManifest) -> io::Result<Vec<String>> {
+    let referenced: HashSet<String> = manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .map(|fm| fm.filename.replace('\\', "/").to_lowercase())
+        .collect();
+
+    Ok(list_install_files(install_dir)?
+        .into_iter()
+        .filter(|file| !referenced.contains(&file.to_lowercase()))
+        .collect())
+}
+
+/// Checks an install against its manifest and reports what is wrong, without repairing or deleting anything
+pub async fn verify_report(
+    manifest: &ParsedManifest,
+    install_dir: PathBuf,
+    mode: VerifyMode,
+    tx: mpsc::Sender<ProgressUpdate>,
+    control: Arc<DownloadControl>,
+) -> Result<VerifyReport, DownloadError> {
+    let integrity = IntegrityDb::load(&install_dir).await;
+    let hash_policy = get_hash_policy();
+
+    let total_files = manifest.file_manifest_list.elements.len();
+    let total_bytes: u64 = manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .map(|fm| fm.file_size)
+        .sum();
+    let checked_bytes = Arc::new(Mutex::new(0u64));
+
+    let results: Vec<Result<(&FileManifest, FileState), DownloadError>> =
+        // By index, since a closure taking the entry by reference makes the future not Send
+        stream::iter(0..total_files)
+            .map(|index| {
+                let fm = &manifest.file_manifest_list.elements[index];
+                let install_dir = &install_dir;
+                let integrity = &integrity;
+                let tx = tx.clone();
+                let control = control.clone();
+                let checked_bytes = checked_bytes.clone();
+
+                async move {
+                    if control.cancelled.load(Ordering::Relaxed) {
+                        return Err(DownloadError::Cancelled);
+                    }
+
+                    let state = check_file(fm, install_dir, integrity, mode, hash_policy).await?;
+
+                    let mut checked = checked_bytes.lock().await;
+                    *checked += fm.file_size;
+                    let _ = tx
+                        .send(ProgressUpdate {
+                            filename: fm.filename.clone(),
+                            downloaded_bytes: *checked,
+                            total_bytes,
+                            total_files,
+                        })
+                        .await;
+
+                    Ok((fm, state))
+                }
+            })
+            .buffer_unordered(10)
+            .collect()
+            .await;
+
+    let mut report = VerifyReport {
+        build_version: manifest.meta.build_version.clone(),
+        install_dir: install_dir.to_string_lossy().to_string(),
+        mode,
+        checked_files: total_files,
+        ..Default::default()
+    };
+    let mut broken: Vec<&FileManifest> = Vec::new();
+
+    for result in results {
+        let (fm, state) = result?;
+        match state {
+            FileState::Valid => {
+                report.valid_files += 1;
+                continue;
+            }
+            FileState::Missing => report.missing.push(fm.filename.clone()),
+            FileState::Corrupt => report.corrupt.push(fm.filename.clone()),
+            FileState::SizeMismatch(actual) => report.size_mismatched.push(SizeMismatch {
+                filename: fm.filename.clone(),
+                expected: fm.file_size,
+                actual,
+            }),
+        }
+        broken.push(fm);
+    }
+
+    report.missing.sort();
+    report.corrupt.sort();
+    report.size_mismatched.sort_by(|a, b| a.filename.cmp(&b.filename));
+    report.repair_bytes = repair_download_size(manifest, &broken);
+    report.extra = find_orphan_files(&install_dir, manifest)?
+        .into_iter()
+        .map(|orphan| orphan.filename)
+        .collect();
+
+    Ok(report)
+}
+
+/// Each chunk is only downloaded once no matter how many broken files use it
+fn repair_download_size(manifest: &ParsedManifest, broken: &[&FileManifest]) -> u64 {
+    let chunk_map: HashMap<u128, &ChunkInfo> = manifest
+        .chunk_data_list
+        .elements
+        .iter()
+        .map(|c| (guid_to_u128(&c.guid), c))
+        .collect();
+
+    let needed: HashSet<u128> = broken
+        .iter()
+        .flat_map(|fm| fm.chunk_parts.iter().map(|cp| guid_to_u128(&cp.guid)))
+        .collect();
+
+    needed
+        .iter()
+        .filter_map(|guid| chunk_map.get(guid))
+        .map(|chunk| chunk.file_size.max(0) as u64)
+        .sum()
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::config::installed::get_installed_objects;
+use crate::config::install_state::InstallState;
+use crate::manifest::downloader::download_utils::guid_to_u128;
+use crate::manifest::downloader::find_cached_manifest;
+use crate::manifest::ParsedManifest;
+use crate::operations::operation_info::normalize_install_dir;
+
+/// Where a range of chunk data sits inside a file of an existing install
+#[derive(Clone, Debug)]
+pub struct ChunkLocation {
+    /// Index into the other installs, or None for the install being written
+    pub install: Option<usize>,
+    pub filename: String,
+    pub chunk_offset: u32,
+    pub size: u32,
+    pub file_offset: u64,
+}
+
+impl ChunkLocation {
+    fn covers(&self, offset: u32, size: u32) -> bool {
+        self.chunk_offset <= offset
+            && offset as u64 + size as u64 <= self.chunk_offset as u64 + self.size as u64
+    }
+}
+
+/// A chunk range found in a file that is already on disk
+pub struct ReusedRange<'a> {
+    /// The other install holding the file, or None when it is in the install being written
+    pub install_dir: Option<&'a Path>,
+    pub filename: &'a str,
+    pub offset: u64,
+}
+
+impl ReusedRange<'_> {
+    pub fn path(&self, own_install_dir: &Path) -> PathBuf {
+        self.install_dir.unwrap_or(own_install_dir).join(self.filename)
+    }
+}
+
+/// Index of every chunk range in an existing install, and in any other installs added to it, so any chunk part can be
+/// copied from whichever file already holds it
+#[derive(Default)]
+pub struct ReuseIndex {
+    locations: HashMap<u128, Vec<ChunkLocation>>,
+    other_installs: Vec<PathBuf>,
+}
+
+impl ReuseIndex {
+    pub fn from_manifest(manifest: &ParsedManifest) -> Self {
+        let mut index = Self::default();
+        index.add_manifest(None, manifest);
+        index
+    }
+
+    /// Adds the files of another install, which are only used when the install being written doesn't hold a range itself
+    pub fn add_install(&mut self, install_dir: PathBuf, manifest: &ParsedManifest) {
+        self.other_installs.push(install_dir);
+        self.add_manifest(Some(self.other_installs.len() - 1), manifest);
+    }
+
+    fn add_manifest(&mut self, install: Option<usize>, manifest: &ParsedManifest) {
+        for file in &manifest.file_manifest_list.elements {
+            let mut file_offset = 0u64;
+            for cp in &file.chunk_parts {
+                self.locations
+                    .entry(guid_to_u128(&cp.guid))
+                    .or_default()
+                    .push(ChunkLocation {
+                        install,
+                        filename: file.filename.clone(),
+                        chunk_offset: cp.offset,
+                        size: cp.size,
+                        file_offset,
+                    });
+                file_offset += cp.size as u64;
+            }
+        }
+    }
+
+    /// Finds a file and byte offset holding the given chunk range, preferring the file being written so it doesn't depend on others.
+    /// Locations of the install being written are indexed first, so they win over other installs.
+    pub fn find(&self, filename: &str, guid: u128, offset: u32, size: u32) -> Option<ReusedRange<'_>> {
+        let candidates = self.locations.get(&guid)?;
+        let location = candidates
+            .iter()
+            .find(|l| l.install.is_none() && l.filename == filename && l.covers(offset, size))
+            .or_else(|| candidates.iter().find(|l| l.covers(offset, size)))?;
+
+        Some(ReusedRange {
+            install_dir: location.install.map(|i| self.other_installs[i].as_path()),
+            filename: location.filename.as_str(),
+            offset: location.file_offset + (offset - location.chunk_offset) as u64,
+        })
+    }
+}
+
+/// The other installs whose files are settled and whose build manifest is cached, to copy chunks from.
+/// An install that is being written or needs a repair is left out, since its files may not match its manifest.
+pub async fn other_install_sources(install_dir: &Path) -> Vec<(PathBuf, ParsedManifest)> {
+    let own = normalize_install_dir(&install_dir.to_string_lossy());
+    let mut sources = Vec::new();
+
+    for installed in get_installed_objects().await.unwrap_or_default() {
+        if normalize_install_dir(&installed.installation_location) == own
+            || !matches!(installed.state, InstallState::Installed | InstallState::UpdateAvailable)
+        {
+            continue;
+        }
+
+        match find_cached_manifest(&installed.app_version).await {
+            Ok((_, manifest)) => sources.push((PathBuf::from(installed.installation_location), manifest)),
+            Err(e) => eprintln!("Can't reuse chunks from {}: {}", installed.installation_location, e),
+        }
+    }
+
+    sources
+}
+
+/// Reads a reused range out of an existing file
+pub async fn read_range(path: &Path, offset: u64, size: u32) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path).await?;
+    let mut buffer = vec![0u8; size as usize];
+    file.seek(SeekFrom::Start(offset)).await?;
+    file.read_exact(&mut buffer).await?;
+    Ok(buffer)
+}
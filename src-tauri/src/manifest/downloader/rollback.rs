@@ -0,0 +1,108 @@
+use futures::stream::{self, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::config::installed::{get_object_by_location, update_object_by_install_id};
+use crate::manifest::chunk_data::ChunkInfo;
+use crate::manifest::downloader::download_utils::{chunk_key, guid_to_u128, object_exists};
+use crate::manifest::downloader::downloader::download_game;
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::find_cached_manifest;
+use crate::manifest::downloader::progress_update::ProgressUpdate;
+use crate::manifest::downloader::reuse::{other_install_sources, ReuseIndex};
+use crate::manifest::ParsedManifest;
+use crate::DownloadControl;
+
+/// Chunks the target build needs that can't be copied out of the current install
+pub fn chunks_to_fetch<'a>(target: &'a ParsedManifest, reuse_index: &ReuseIndex) -> Vec<&'a ChunkInfo> {
+    let chunk_map: HashMap<u128, &ChunkInfo> = target
+        .chunk_data_list
+        .elements
+        .iter()
+        .map(|c| (guid_to_u128(&c.guid), c))
+        .collect();
+
+    let mut needed: HashSet<u128> = HashSet::new();
+    for fm in &target.file_manifest_list.elements {
+        for cp in &fm.chunk_parts {
+            let guid = guid_to_u128(&cp.guid);
+            if reuse_index.find(&fm.filename, guid, cp.offset, cp.size).is_none() {
+                needed.insert(guid);
+            }
+        }
+    }
+
+    needed.iter().filter_map(|guid| chunk_map.get(guid).copied()).collect()
+}
+
+/// Returns the keys of every chunk that can no longer be downloaded from the bucket
+pub async fn find_unavailable_chunks(bucket: &str, chunks: &[&ChunkInfo]) -> Vec<String> {
+    let keys: Vec<String> = chunks.iter().map(|chunk| chunk_key(chunk)).collect();
+    stream::iter(keys)
+        .map(|key| async move {
+            match object_exists(bucket, &key).await {
+                Ok(true) => None,
+                Ok(false) => Some(key),
+                Err(e) => {
+                    // If we can't tell, assume it's gone rather than leave the install half rolled back
+                    eprintln!("Couldn't check chunk {}: {}", key, e);
+                    Some(key)
+                }
+            }
+        })
+        .buffer_unordered(32)
+        .filter_map(|missing| async move { missing })
+        .collect()
+        .await
+}
+
+/// Downgrades an install to a build whose manifest is still cached, only fetching chunks neither it nor the other installs have
+pub async fn rollback_to_build(
+    build_version: String,
+    bucket: String,
+    install_dir: PathBuf,
+    tx: mpsc::Sender<ProgressUpdate>,
+    control: Arc<DownloadControl>,
+) -> Result<(), DownloadError> {
+    let mut installed = get_object_by_location(&install_dir.to_string_lossy())
+        .await
+        .map_err(|_| DownloadError::UnexpectedError)?;
+
+    if installed.app_version == build_version {
+        return Ok(());
+    }
+
+    let (_, target_manifest) = find_cached_manifest(&build_version).await?;
+
+    // Without the installed build's manifest we can't reuse anything, so every chunk has to be downloaded
+    let current_manifest = find_cached_manifest(&installed.app_version)
+        .await
+        .ok()
+        .map(|(_, manifest)| manifest);
+    let mut reuse_index = match &current_manifest {
+        Some(current) => ReuseIndex::from_manifest(current),
+        None => ReuseIndex::default(),
+    };
+    for (other_dir, other_manifest) in other_install_sources(&install_dir).await {
+        reuse_index.add_install(other_dir, &other_manifest);
+    }
+
+    let needed = chunks_to_fetch(&target_manifest, &reuse_index);
+    println!("Rolling back to {}: {} chunks to download", build_version, needed.len());
+
+    let unavailable = find_unavailable_chunks(&bucket, &needed).await;
+    if !unavailable.is_empty() {
+        return Err(DownloadError::ChunksUnavailable(unavailable.len()));
+    }
+
+    download_game(target_manifest, bucket, install_dir, tx, control, current_manifest).await?;
+
+    // The next update diffs against the manifest of `app_version`, so recording the build is all it takes
+    installed.rolled_back_from = Some(installed.app_version.clone());
+    installed.app_version = build_version;
+    update_object_by_install_id(installed)
+        .await
+        .map_err(|_| DownloadError::UnexpectedError)
+}
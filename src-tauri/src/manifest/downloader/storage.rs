@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+use tokio::fs;
+
+use crate::config::installed::get_installed_objects;
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::report::walk_files;
+use crate::manifest::downloader::store::{linked_files, store_usage};
+use crate::manifest::downloader::transaction::{has_pending_journal, staging_dir, STAGING_DIR_NAME};
+use crate::manifest::downloader::{get_cached_manifests, get_manifest_cache_path};
+use crate::manifest::{ManifestError, ParsedManifest};
+
+/// Cached manifests kept besides the ones installs are on, so there is something to roll back to
+pub const MANIFEST_RETENTION: usize = 3;
+
+/// Suffixes of files the launcher builds next to the one they replace, which are only left behind by a crash
+const TEMP_SUFFIXES: &[&str] = &[".tmp", ".link", ".unshare"];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FolderUsage {
+    /// Top-level folder of the install, or `.` for the files directly in it
+    pub folder: String,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TagUsage {
+    /// Install tag, or None for the files every install gets. A file with several tags counts toward each of them.
+    pub tag: Option<String>,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InstallUsage {
+    pub install_id: String,
+    pub install_dir: String,
+    pub build_version: String,
+    pub total_bytes: u64,
+    /// Files of the installed build
+    pub game_bytes: u64,
+    /// Build files that are hard links into the shared store, so they take no space of their own
+    pub shared_bytes: u64,
+    /// Unfinished downloads and the staging folder of updates
+    pub temp_bytes: u64,
+    /// User data, launcher bookkeeping and anything else that isn't part of the build
+    pub other_bytes: u64,
+    pub folders: Vec<FolderUsage>,
+    /// Empty when the manifest of the installed build isn't cached
+    pub tags: Vec<TagUsage>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CachedManifestUsage {
+    pub build_version: String,
+    pub bytes: u64,
+    /// Whether an install is on this build, which keeps it from being cleaned up
+    pub in_use: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StorageReport {
+    pub installs: Vec<InstallUsage>,
+    pub manifest_cache_bytes: u64,
+    pub manifests: Vec<CachedManifestUsage>,
+    pub shared_store_bytes: u64,
+    pub shared_store_files: usize,
+    /// Everything above with hard linked files counted once
+    pub total_bytes: u64,
+}
+
+fn is_temp_file(filename: &str) -> bool {
+    filename.starts_with(&format!("{}/", STAGING_DIR_NAME))
+        || TEMP_SUFFIXES.iter().any(|suffix| filename.to_lowercase().ends_with(suffix))
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Sizes of one install, by kind of file, by top-level folder and by install tag
+pub fn install_usage(
+    install_id: String,
+    install_dir: &Path,
+    build_version: String,
+    manifest: Option<&ParsedManifest>,
+) -> io::Result<InstallUsage> {
+    let build_files: HashMap<String, &Vec<String>> = manifest
+        .map(|manifest| {
+            manifest
+                .file_manifest_list
+                .elements
+                .iter()
+                .map(|fm| (fm.filename.replace('\\', "/").to_lowercase(), &fm.install_tags))
+                .collect()
+        })
+        .unwrap_or_default();
+    let shared = linked_files(install_dir);
+
+    let (mut game_bytes, mut shared_bytes, mut temp_bytes, mut other_bytes) = (0u64, 0u64, 0u64, 0u64);
+    let mut folders: BTreeMap<String, u64> = BTreeMap::new();
+    let mut tags: BTreeMap<Option<String>, u64> = BTreeMap::new();
+
+    let files = match install_dir.is_dir() {
+        true => walk_files(install_dir)?,
+        false => Vec::new(),
+    };
+    for file in files {
+        let size = file_size(&install_dir.join(&file));
+        let folder = match file.split_once('/') {
+            Some((folder, _)) => folder.to_string(),
+            None => ".".to_string(),
+        };
+        *folders.entry(folder).or_default() += size;
+
+        let key = file.to_lowercase();
+        if is_temp_file(&file) {
+            temp_bytes += size;
+        } else if let Some(file_tags) = build_files.get(&key) {
+            game_bytes += size;
+            if shared.contains(&key) {
+                shared_bytes += size;
+            }
+
+            match file_tags.is_empty() {
+                true => *tags.entry(None).or_default() += size,
+                false => {
+                    for tag in file_tags.iter() {
+                        *tags.entry(Some(tag.clone())).or_default() += size;
+                    }
+                }
+            }
+        } else {
+            other_bytes += size;
+        }
+    }
+
+    Ok(InstallUsage {
+        install_id,
+        install_dir: install_dir.to_string_lossy().to_string(),
+        build_version,
+        total_bytes: game_bytes + temp_bytes + other_bytes,
+        game_bytes,
+        shared_bytes,
+        temp_bytes,
+        other_bytes,
+        folders: folders
+            .into_iter()
+            .map(|(folder, bytes)| FolderUsage { folder, bytes })
+            .collect(),
+        tags: tags
+            .into_iter()
+            .map(|(tag, bytes)| TagUsage { tag, bytes })
+            .collect(),
+    })
+}
+
+/// Sizes of every install, the manifest cache and the shared store
+pub async fn storage_report() -> Result<StorageReport, DownloadError> {
+    let installed = get_installed_objects()
+        .await
+        .map_err(|_| DownloadError::UnexpectedError)?;
+    let manifests = get_cached_manifests().await?;
+    let in_use: HashSet<&str> = installed.iter().map(|object| object.app_version.as_str()).collect();
+
+    let manifest_usage: Vec<CachedManifestUsage> = manifests
+        .iter()
+        .map(|(path, manifest)| CachedManifestUsage {
+            build_version: manifest.meta.build_version.clone(),
+            bytes: file_size(path),
+            in_use: in_use.contains(manifest.meta.build_version.as_str()),
+        })
+        .collect();
+
+    // Walking every install makes a lot of blocking file system calls, so it runs off the async threads
+    let (installs, manifest_cache_bytes, (shared_store_files, shared_store_bytes)) =
+        tokio::task::spawn_blocking(move || -> io::Result<_> {
+            let mut installs = Vec::new();
+            for object in &installed {
+                let manifest = manifests
+                    .iter()
+                    .find(|(_, manifest)| manifest.meta.build_version == object.app_version)
+                    .map(|(_, manifest)| manifest);
+
+                installs.push(install_usage(
+                    object.install_id.clone(),
+                    Path::new(&object.installation_location),
+                    object.app_version.clone(),
+                    manifest,
+                )?);
+            }
+
+            let manifest_cache_bytes: u64 = get_manifest_cache_path()
+                .ok()
+                .filter(|path| path.is_dir())
+                .and_then(|path| walk_files(&path).ok().map(|files| (path, files)))
+                .map(|(path, files)| files.iter().map(|file| file_size(&path.join(file))).sum())
+                .unwrap_or(0);
+
+            Ok((installs, manifest_cache_bytes, store_usage()))
+        })
+        .await??;
+
+    let unshared_install_bytes: u64 = installs
+        .iter()
+        .map(|install| install.total_bytes - install.shared_bytes)
+        .sum();
+
+    Ok(StorageReport {
+        installs,
+        manifest_cache_bytes,
+        manifests: manifest_usage,
+        shared_store_bytes,
+        shared_store_files,
+        total_bytes: unshared_install_bytes + manifest_cache_bytes + shared_store_bytes,
+    })
+}
+
+/// Deletes cached manifests no install is on, keeping the newest `keep` of them to roll back to. The newest cached
+/// manifest always stays, since a download in progress is on it before any install records it. Returns how many bytes
+/// were freed.
+pub async fn prune_manifest_cache(keep: usize) -> Result<u64, ManifestError> {
+    let in_use: HashSet<String> = get_installed_objects()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|object| object.app_version)
+        .collect();
+
+    let mut cached: Vec<(std::path::PathBuf, String, std::time::SystemTime)> = get_cached_manifests()
+        .await?
+        .into_iter()
+        .filter_map(|(path, manifest)| {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((path, manifest.meta.build_version, modified))
+        })
+        .collect();
+
+    // Sort newest first
+    cached.sort_by_key(|cached| std::cmp::Reverse(cached.2));
+
+    let unused = cached
+        .into_iter()
+        .skip(1)
+        .filter(|(_, build_version, _)| !in_use.contains(build_version));
+
+    let mut freed = 0u64;
+    for (path, _, _) in unused.skip(keep) {
+        let size = file_size(&path);
+        fs::remove_file(&path).await?;
+        println!("Removed cached manifest {:?}", path);
+        freed += size;
+    }
+
+    Ok(freed)
+}
+
+/// Removes what unfinished downloads, links and copies left in an install. The staging folder stays while it holds an
+/// update journal, which recovery still needs to roll the update back. Returns how many bytes were freed.
+pub async fn remove_stale_temp_files(install_dir: &Path, manifest: Option<&ParsedManifest>) -> Result<u64, DownloadError> {
+    if !install_dir.is_dir() {
+        return Ok(0);
+    }
+
+    // A build could ship a file that only looks like a leftover
+    let build_files: HashSet<String> = manifest
+        .map(|manifest| {
+            manifest
+                .file_manifest_list
+                .elements
+                .iter()
+                .map(|fm| fm.filename.replace('\\', "/").to_lowercase())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut freed = 0u64;
+    for file in walk_files(install_dir)? {
+        if file.starts_with(&format!("{}/", STAGING_DIR_NAME))
+            || !is_temp_file(&file)
+            || build_files.contains(&file.to_lowercase())
+        {
+            continue;
+        }
+
+        let path = install_dir.join(&file);
+        let size = file_size(&path);
+        match fs::remove_file(&path).await {
+            Ok(()) => freed += size,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let staging = staging_dir(install_dir);
+    if staging.is_dir() && !has_pending_journal(install_dir) {
+        let size: u64 = walk_files(&staging)?
+            .iter()
+            .map(|file| file_size(&staging.join(file)))
+            .sum();
+        fs::remove_dir_all(&staging).await?;
+        freed += size;
+    }
+
+    Ok(freed)
+}
@@ -0,0 +1,387 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::config::get_shared_store_enabled;
+use crate::config::installed::get_object_by_location;
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::find_cached_manifest;
+use crate::manifest::downloader::report::walk_files;
+use crate::manifest::ParsedManifest;
+use crate::operations::operation_info::{is_within, normalize_install_dir};
+
+/// Files smaller than this aren't worth a link and a reference
+const MIN_SHARED_SIZE: u64 = 1024 * 1024;
+
+/// Serializes changes to the references between operations finishing at the same time
+static STORE_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+
+fn store_lock() -> &'static Mutex<()> {
+    STORE_LOCK.get_or_init(|| Mutex::new(()))
+}
+
+/// Which install files are hard links to each stored file, keyed by the file's SHA1 from the manifest
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StoreReferences {
+    #[serde(default)]
+    pub files: HashMap<String, Vec<String>>,
+}
+
+/// Utility function that returns the path to the shared store folder
+pub fn get_store_path() -> io::Result<PathBuf> {
+    let local_app_data = std::env::var("LOCALAPPDATA").map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "LOCALAPPDATA environment variable not found",
+        )
+    })?;
+
+    Ok(PathBuf::from(local_app_data)
+        .join("RealityLauncher")
+        .join("Store"))
+}
+
+fn entry_path(store: &Path, hash: &str) -> PathBuf {
+    store.join(&hash[..2]).join(hash)
+}
+
+fn references_path(store: &Path) -> PathBuf {
+    store.join("References.json")
+}
+
+/// A path next to a file, for the copy or link that replaces it
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+async fn read_references(store: &Path) -> StoreReferences {
+    match fs::read(references_path(store)).await {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            eprintln!("Ignoring unreadable store references: {}", e);
+            StoreReferences::default()
+        }),
+        Err(_) => StoreReferences::default(),
+    }
+}
+
+/// Written to a temp file first, so a crash mid-write never loses the references
+async fn write_references(store: &Path, references: &StoreReferences) -> Result<(), DownloadError> {
+    fs::create_dir_all(store).await?;
+
+    let path = references_path(store);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(references)?).await?;
+    fs::rename(&tmp, &path).await?;
+    Ok(())
+}
+
+/// Forgets every reference from files inside an install
+fn drop_install_references(references: &mut StoreReferences, install_dir: &Path) {
+    let dir = normalize_install_dir(&install_dir.to_string_lossy());
+    for paths in references.files.values_mut() {
+        paths.retain(|path| !is_within(&normalize_install_dir(path), &dir));
+    }
+}
+
+/// Removes the stored files nothing links to anymore. Returns how many bytes were freed.
+async fn collect_garbage(store: &Path, references: &mut StoreReferences) -> u64 {
+    let unused: Vec<String> = references
+        .files
+        .iter()
+        .filter(|(_, paths)| paths.is_empty())
+        .map(|(hash, _)| hash.clone())
+        .collect();
+
+    let mut freed = 0u64;
+    for hash in unused {
+        references.files.remove(&hash);
+
+        let path = entry_path(store, &hash);
+        let size = fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+        if fs::remove_file(&path).await.is_ok() {
+            freed += size;
+        }
+    }
+
+    freed
+}
+
+/// Swaps a file for a hard link to the stored copy. The link is made next to it first, so the file is never missing.
+async fn link_from_store(stored: &Path, path: &Path) -> io::Result<()> {
+    let link = sibling(path, ".link");
+    let _ = fs::remove_file(&link).await;
+    fs::hard_link(stored, &link).await?;
+
+    if let Err(e) = fs::rename(&link, path).await {
+        let _ = fs::remove_file(&link).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+async fn add_to_store(path: &Path, stored: &Path) -> io::Result<()> {
+    if let Some(parent) = stored.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::hard_link(path, stored).await
+}
+
+/// Gives a file a copy of its own in place of a hard link, without it ever going missing
+async fn copy_in_place(path: &Path) -> io::Result<()> {
+    let copy = sibling(path, ".unshare");
+    fs::copy(path, &copy).await?;
+
+    if let Err(e) = fs::rename(&copy, path).await {
+        let _ = fs::remove_file(&copy).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Whether a file has the SHA1 the manifest gives it. Hashed on a blocking thread, since shared files are large.
+async fn has_sha1(path: &Path, expected: &[u8]) -> bool {
+    let path = path.to_path_buf();
+    let expected = expected.to_vec();
+    let hashed = tokio::task::spawn_blocking(move || -> io::Result<bool> {
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = Sha1::new();
+        io::copy(&mut file, &mut hasher)?;
+        Ok(hasher.finalize().as_slice() == expected.as_slice())
+    })
+    .await;
+
+    matches!(hashed, Ok(Ok(true)))
+}
+
+/// Links the files of an install to the store, adding the ones the store doesn't have yet. Files on another volume
+/// than the store can't be hard linked and stay plain copies, and files that don't match the manifest are left out.
+/// Every reference from the install is rebuilt, so this can run again after anything replaced its files.
+pub async fn link_install(install_dir: &Path, manifest: &ParsedManifest) -> Result<(), DownloadError> {
+    let store = get_store_path()?;
+    let _guard = store_lock().lock().await;
+
+    let mut references = read_references(&store).await;
+    drop_install_references(&mut references, install_dir);
+
+    let (mut shared_files, mut shared_bytes, mut copies, mut mismatched) = (0usize, 0u64, 0usize, 0usize);
+    for fm in manifest
+        .file_manifest_list
+        .elements
+        .iter()
+        .filter(|fm| fm.file_size >= MIN_SHARED_SIZE && !fm.hash.is_empty())
+    {
+        let path = install_dir.join(&fm.filename);
+        match fs::metadata(&path).await {
+            Ok(metadata) if metadata.len() == fm.file_size => {}
+            _ => continue,
+        }
+
+        // A damaged file would spread to every install linking to it, so it stays out until a repair fixes it
+        if !has_sha1(&path, &fm.hash).await {
+            mismatched += 1;
+            continue;
+        }
+
+        let hash = hex::encode(&fm.hash);
+        let stored = entry_path(&store, &hash);
+        let stored_size = fs::metadata(&stored).await.map(|m| m.len()).ok();
+
+        let linked = match stored_size {
+            Some(size) if size == fm.file_size && has_sha1(&stored, &fm.hash).await => {
+                link_from_store(&stored, &path).await
+            }
+            _ => {
+                // Not stored yet, or what is stored under this hash isn't the file, so the install's copy becomes the stored one
+                let _ = fs::remove_file(&stored).await;
+                add_to_store(&path, &stored).await
+            }
+        };
+
+        match linked {
+            Ok(()) => {
+                references
+                    .files
+                    .entry(hash)
+                    .or_default()
+                    .push(path.to_string_lossy().to_string());
+                shared_files += 1;
+                shared_bytes += fm.file_size;
+            }
+            // Most likely another volume, where the install keeps a copy of its own
+            Err(_) => copies += 1,
+        }
+    }
+
+    collect_garbage(&store, &mut references).await;
+    write_references(&store, &references).await?;
+
+    println!(
+        "Shared {} files ({} bytes) of {:?} through the store, {} kept as copies, {} left out for not matching the manifest",
+        shared_files, shared_bytes, install_dir, copies, mismatched
+    );
+    Ok(())
+}
+
+/// Drops the references from an install that is about to be deleted or moved, removing stored files no other install
+/// links to. Returns how many bytes were freed in the store.
+pub async fn release_install(install_dir: &Path) -> Result<u64, DownloadError> {
+    let store = get_store_path()?;
+    if !references_path(&store).exists() {
+        return Ok(0);
+    }
+
+    let _guard = store_lock().lock().await;
+
+    let mut references = read_references(&store).await;
+    drop_install_references(&mut references, install_dir);
+    let freed = collect_garbage(&store, &mut references).await;
+    write_references(&store, &references).await?;
+
+    Ok(freed)
+}
+
+/// Gives a linked file a copy of its own before it gets written in place, which would otherwise change every install
+/// linking to it. The other installs keep linking to the stored file, which only leaves the store once none does.
+pub async fn unshare_file(path: &Path) -> Result<(), DownloadError> {
+    let store = get_store_path()?;
+    if !references_path(&store).exists() {
+        return Ok(());
+    }
+
+    let _guard = store_lock().lock().await;
+
+    let mut references = read_references(&store).await;
+    let target = normalize_install_dir(&path.to_string_lossy());
+    let Some(hash) = references
+        .files
+        .iter()
+        .find(|(_, paths)| paths.iter().any(|p| normalize_install_dir(p) == target))
+        .map(|(hash, _)| hash.clone())
+    else {
+        return Ok(());
+    };
+
+    copy_in_place(path).await?;
+
+    if let Some(paths) = references.files.get_mut(&hash) {
+        paths.retain(|p| normalize_install_dir(p) != target);
+        if paths.is_empty() {
+            references.files.remove(&hash);
+            let _ = fs::remove_file(entry_path(&store, &hash)).await;
+        }
+    }
+
+    write_references(&store, &references).await
+}
+
+/// Links the files of an install to the store when the shared store is switched on, using the manifest of the build
+/// it has. Not sharing only costs disk space, so a failure is logged rather than failing the operation around it.
+pub async fn share_install(install_dir: &str) {
+    if !get_shared_store_enabled() {
+        return;
+    }
+
+    let result = async {
+        let installed = get_object_by_location(install_dir)
+            .await
+            .map_err(|_| DownloadError::UnexpectedError)?;
+        let (_, manifest) = find_cached_manifest(&installed.app_version).await?;
+        link_install(Path::new(install_dir), &manifest).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        eprintln!("Couldn't share the files of {} through the store: {}", install_dir, e);
+    }
+}
+
+/// Files of an install recorded as links into the store, relative to it, lowercased and with forward slashes
+pub fn linked_files(install_dir: &Path) -> HashSet<String> {
+    let references = get_store_path()
+        .and_then(|store| std::fs::read(references_path(&store)))
+        .ok()
+        .and_then(|data| serde_json::from_slice::<StoreReferences>(&data).ok())
+        .unwrap_or_default();
+
+    let dir = format!("{}\\", normalize_install_dir(&install_dir.to_string_lossy()));
+    references
+        .files
+        .into_values()
+        .flatten()
+        .filter_map(|path| {
+            normalize_install_dir(&path)
+                .strip_prefix(&dir)
+                .map(|relative| relative.replace('\\', "/"))
+        })
+        .collect()
+}
+
+/// How many files the store holds and how many bytes they take, each counted once however many installs link to it.
+/// The references file and its temp copy aren't stored files, so they're left out.
+pub fn store_usage() -> (usize, u64) {
+    let Ok(store) = get_store_path() else {
+        return (0, 0);
+    };
+    let references = references_path(&store);
+    let references_tmp = references.with_extension("json.tmp");
+
+    walk_files(&store)
+        .map(|files| {
+            files
+                .iter()
+                .map(|file| store.join(file))
+                .filter(|path| *path != references && *path != references_tmp)
+                .filter_map(|path| std::fs::metadata(path).ok())
+                .fold((0usize, 0u64), |(count, bytes), metadata| (count + 1, bytes + metadata.len()))
+        })
+        .unwrap_or((0, 0))
+}
+
+/// Drops references to install files that are gone and removes stored files nothing links to, including ones left
+/// behind by a crash before they were recorded. Returns how many bytes were freed.
+pub async fn clean_store() -> Result<u64, DownloadError> {
+    let store = get_store_path()?;
+    if !store.is_dir() {
+        return Ok(0);
+    }
+
+    let _guard = store_lock().lock().await;
+
+    let mut references = read_references(&store).await;
+    for paths in references.files.values_mut() {
+        paths.retain(|path| Path::new(path).exists());
+    }
+    let mut freed = collect_garbage(&store, &mut references).await;
+
+    let references_file = references_path(&store);
+    for file in walk_files(&store)? {
+        let path = store.join(&file);
+        if path == references_file {
+            continue;
+        }
+
+        let recorded = path
+            .file_name()
+            .map(|hash| references.files.contains_key(hash.to_string_lossy().as_ref()))
+            .unwrap_or(false);
+        if recorded {
+            continue;
+        }
+
+        let size = fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+        if fs::remove_file(&path).await.is_ok() {
+            freed += size;
+        }
+    }
+
+    write_references(&store, &references).await?;
+    Ok(freed)
+}
@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use crate::manifest::downloader::errors::DownloadError;
+
+/// Folder inside the install directory holding the update journal and the files an update replaced
+pub const STAGING_DIR_NAME: &str = ".reality-staging";
+const JOURNAL_FILE_NAME: &str = "journal.log";
+const BACKUP_DIR_NAME: &str = "backup";
+
+/// One line of the update journal. Entries are written before the step they describe so a rollback never misses one.
+#[derive(Debug, PartialEq, Eq)]
+pub enum JournalEntry {
+    /// A verified file is waiting at its temp path
+    Stage(String),
+    /// The previous version of a file is being moved into the backup folder
+    Replace(String),
+    /// A staged file is being moved into place
+    Install(String),
+    /// Every staged file is in place, the backups are no longer needed
+    Commit,
+}
+
+impl JournalEntry {
+    fn to_line(&self) -> String {
+        match self {
+            JournalEntry::Stage(filename) => format!("stage {}\n", filename),
+            JournalEntry::Replace(filename) => format!("replace {}\n", filename),
+            JournalEntry::Install(filename) => format!("install {}\n", filename),
+            JournalEntry::Commit => "commit\n".to_string(),
+        }
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        match line.split_once(' ') {
+            Some(("stage", filename)) => Some(JournalEntry::Stage(filename.to_string())),
+            Some(("replace", filename)) => Some(JournalEntry::Replace(filename.to_string())),
+            Some(("install", filename)) => Some(JournalEntry::Install(filename.to_string())),
+            None if line == "commit" => Some(JournalEntry::Commit),
+            _ => None,
+        }
+    }
+}
+
+pub fn staging_dir(install_dir: &Path) -> PathBuf {
+    install_dir.join(STAGING_DIR_NAME)
+}
+
+fn journal_path(install_dir: &Path) -> PathBuf {
+    staging_dir(install_dir).join(JOURNAL_FILE_NAME)
+}
+
+fn backup_path(install_dir: &Path, filename: &str) -> PathBuf {
+    staging_dir(install_dir).join(BACKUP_DIR_NAME).join(filename)
+}
+
+/// Where a file is built before it gets committed
+pub fn tmp_path(install_dir: &Path, filename: &str) -> PathBuf {
+    install_dir.join(format!("{}.tmp", filename))
+}
+
+/// Whether an update was interrupted partway through its commit
+pub fn has_pending_journal(install_dir: &Path) -> bool {
+    journal_path(install_dir).exists()
+}
+
+/// Reads the journal of an interrupted update
+pub async fn read_journal(install_dir: &Path) -> Result<Vec<JournalEntry>, DownloadError> {
+    match fs::read_to_string(journal_path(install_dir)).await {
+        Ok(content) => Ok(content.lines().filter_map(JournalEntry::from_line).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(DownloadError::IoError(e)),
+    }
+}
+
+async fn append_journal(journal: &mut fs::File, entry: JournalEntry) -> Result<(), DownloadError> {
+    journal.write_all(entry.to_line().as_bytes()).await?;
+    journal.sync_data().await?;
+    Ok(())
+}
+
+/// Moves every staged file into place. If any step fails the install is rolled back to the previous build.
+pub async fn commit_staged_files(install_dir: &Path, staged: &[String]) -> Result<(), DownloadError> {
+    if staged.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(staging_dir(install_dir)).await?;
+    let mut journal = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path(install_dir))
+        .await?;
+
+    for filename in staged {
+        append_journal(&mut journal, JournalEntry::Stage(filename.clone())).await?;
+    }
+
+    if let Err(e) = apply_staged_files(install_dir, staged, &mut journal).await {
+        drop(journal);
+        eprintln!("Committing update failed: {}. Rolling back...", e);
+        rollback_journal(install_dir).await?;
+        return Err(DownloadError::RolledBack(e.to_string()));
+    }
+
+    append_journal(&mut journal, JournalEntry::Commit).await?;
+    drop(journal);
+
+    finish_journal(install_dir).await
+}
+
+async fn apply_staged_files(
+    install_dir: &Path,
+    staged: &[String],
+    journal: &mut fs::File,
+) -> Result<(), DownloadError> {
+    for filename in staged {
+        let final_path = install_dir.join(filename);
+
+        if final_path.exists() {
+            let backup = backup_path(install_dir, filename);
+            if let Some(parent) = backup.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+
+            append_journal(journal, JournalEntry::Replace(filename.clone())).await?;
+            fs::rename(&final_path, &backup).await?;
+        }
+
+        append_journal(journal, JournalEntry::Install(filename.clone())).await?;
+        fs::rename(tmp_path(install_dir, filename), &final_path).await?;
+    }
+
+    Ok(())
+}
+
+/// Undoes an uncommitted update from its journal, restoring every replaced file. A committed journal is just cleaned up.
+pub async fn rollback_journal(install_dir: &Path) -> Result<(), DownloadError> {
+    let entries = read_journal(install_dir).await?;
+
+    if entries.contains(&JournalEntry::Commit) {
+        return finish_journal(install_dir).await;
+    }
+
+    let replaced: HashSet<&String> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            JournalEntry::Replace(filename) => Some(filename),
+            _ => None,
+        })
+        .collect();
+
+    // Undo in reverse so each file ends up exactly as it was before the update
+    for entry in entries.iter().rev() {
+        match entry {
+            JournalEntry::Install(filename) if !replaced.contains(filename) => {
+                // A file the previous build didn't have
+                remove_if_exists(&install_dir.join(filename)).await?;
+            }
+            JournalEntry::Replace(filename) => {
+                let backup = backup_path(install_dir, filename);
+                if backup.exists() {
+                    fs::rename(&backup, install_dir.join(filename)).await?;
+                }
+            }
+            JournalEntry::Stage(filename) => {
+                remove_if_exists(&tmp_path(install_dir, filename)).await?;
+            }
+            _ => {}
+        }
+    }
+
+    finish_journal(install_dir).await
+}
+
+/// Removes staged temp files of an update that never reached its commit, along with any folders only they were in
+pub async fn discard_staged_files<'a>(install_dir: &Path, filenames: impl IntoIterator<Item = &'a String>) {
+    for filename in filenames {
+        let path = tmp_path(install_dir, filename);
+        if remove_if_exists(&path).await.is_ok() {
+            prune_empty_dirs(install_dir, &path).await;
+        }
+    }
+}
+
+/// Removes the now empty folders above a deleted file, stopping at the install directory or the first folder still in use
+pub async fn prune_empty_dirs(install_dir: &Path, removed_file: &Path) {
+    let mut dir = removed_file.parent();
+    while let Some(current) = dir {
+        if current == install_dir || !current.starts_with(install_dir) || fs::remove_dir(current).await.is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// Deletes the journal and the backups once they are no longer needed
+async fn finish_journal(install_dir: &Path) -> Result<(), DownloadError> {
+    let dir = staging_dir(install_dir);
+    if dir.exists() {
+        fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+}
+
+async fn remove_if_exists(path: &Path) -> Result<(), DownloadError> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(DownloadError::IoError(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty install folder of its own for one test
+    fn test_install_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("reality-transaction-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(staging_dir(&dir).join(BACKUP_DIR_NAME)).unwrap();
+        dir
+    }
+
+    fn write_journal(install_dir: &Path, entries: &[JournalEntry]) {
+        let content: String = entries.iter().map(JournalEntry::to_line).collect();
+        std::fs::write(journal_path(install_dir), content).unwrap();
+    }
+
+    #[test]
+    fn journal_lines_round_trip() {
+        for entry in [
+            JournalEntry::Stage("Engine/Binaries/Game.exe".to_string()),
+            JournalEntry::Replace("Content/Paks/pak 1.pak".to_string()),
+            JournalEntry::Install("Game.ini".to_string()),
+            JournalEntry::Commit,
+        ] {
+            assert_eq!(JournalEntry::from_line(entry.to_line().trim_end()), Some(entry));
+        }
+        assert_eq!(JournalEntry::from_line("rename Game.ini"), None);
+    }
+
+    #[tokio::test]
+    async fn rollback_restores_the_previous_build() {
+        let dir = test_install_dir("rollback");
+
+        // Interrupted after replacing changed.txt and installing added.txt, before pending.txt was moved into place
+        std::fs::write(backup_path(&dir, "changed.txt"), "old").unwrap();
+        std::fs::write(dir.join("changed.txt"), "new").unwrap();
+        std::fs::write(dir.join("added.txt"), "new").unwrap();
+        std::fs::write(tmp_path(&dir, "pending.txt"), "new").unwrap();
+        std::fs::write(dir.join("pending.txt"), "old").unwrap();
+        write_journal(
+            &dir,
+            &[
+                JournalEntry::Stage("changed.txt".to_string()),
+                JournalEntry::Stage("added.txt".to_string()),
+                JournalEntry::Stage("pending.txt".to_string()),
+                JournalEntry::Replace("changed.txt".to_string()),
+                JournalEntry::Install("changed.txt".to_string()),
+                JournalEntry::Install("added.txt".to_string()),
+            ],
+        );
+
+        rollback_journal(&dir).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(dir.join("changed.txt")).unwrap(), "old");
+        assert!(!dir.join("added.txt").exists());
+        assert_eq!(std::fs::read_to_string(dir.join("pending.txt")).unwrap(), "old");
+        assert!(!tmp_path(&dir, "pending.txt").exists());
+        assert!(!has_pending_journal(&dir));
+        assert!(!staging_dir(&dir).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn committed_journal_keeps_the_new_build() {
+        let dir = test_install_dir("committed");
+
+        std::fs::write(backup_path(&dir, "changed.txt"), "old").unwrap();
+        std::fs::write(dir.join("changed.txt"), "new").unwrap();
+        write_journal(
+            &dir,
+            &[
+                JournalEntry::Stage("changed.txt".to_string()),
+                JournalEntry::Replace("changed.txt".to_string()),
+                JournalEntry::Install("changed.txt".to_string()),
+                JournalEntry::Commit,
+            ],
+        );
+
+        rollback_journal(&dir).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(dir.join("changed.txt")).unwrap(), "new");
+        assert!(!staging_dir(&dir).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::path::{Component, Path};
+
+use serde::Serialize;
+
+use crate::config::installed::get_object_by_location;
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::{find_cached_manifest, get_cached_manifests};
+use crate::manifest::downloader::integrity::INTEGRITY_DB_FILE_NAME;
+use crate::manifest::downloader::report::walk_files;
+use crate::manifest::downloader::transaction::STAGING_DIR_NAME;
+use crate::manifest::ManifestError;
+use crate::operations::operation_info::{is_within, normalize_install_dir};
+
+/// Files and folders the launcher itself puts into an install, which go along with the game
+const LAUNCHER_ARTIFACTS: &[&str] = &[
+    "FortniteGame/Binaries/Win64/Reality/",
+    "FortniteGame/Binaries/Win64/RealityLauncher.exe",
+];
+
+/// Environment variables pointing at folders an install must never be in, or contain
+const PROTECTED_DIR_VARS: &[&str] = &[
+    "SystemRoot",
+    "windir",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+    "ProgramW6432",
+    "ProgramData",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "PUBLIC",
+    "HOME",
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedFile {
+    pub filename: String,
+    pub size: u64,
+}
+
+/// Exactly what an uninstall would delete, and what it would leave alone
+#[derive(Clone, Debug, Serialize)]
+pub struct UninstallPlan {
+    pub install_dir: String,
+    pub files: Vec<PlannedFile>,
+    pub total_bytes: u64,
+    /// Files that stay because they don't belong to the game, such as user settings
+    pub kept: Vec<String>,
+}
+
+/// Refuses drive roots, relative paths and system or profile folders, along with any folder containing one of them
+pub fn check_uninstall_dir(install_dir: &Path) -> Result<(), DownloadError> {
+    let unsafe_path = || DownloadError::UnsafeUninstallPath(install_dir.to_string_lossy().to_string());
+
+    if !install_dir.is_absolute() || install_dir.parent().is_none() {
+        return Err(unsafe_path());
+    }
+
+    // Anything with a `..` could resolve somewhere else entirely
+    if install_dir.components().any(|c| c == Component::ParentDir) {
+        return Err(unsafe_path());
+    }
+
+    let target = normalize_install_dir(&install_dir.to_string_lossy());
+    let mut protected: Vec<String> = PROTECTED_DIR_VARS
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(|dir| normalize_install_dir(&dir))
+        .collect();
+
+    // The folder holding every user profile
+    if let Some(users) = std::env::var("USERPROFILE")
+        .ok()
+        .and_then(|profile| Path::new(&profile).parent().map(|p| p.to_string_lossy().to_string()))
+    {
+        protected.push(normalize_install_dir(&users));
+    }
+
+    let hits_protected = protected
+        .iter()
+        .filter(|dir| !dir.is_empty())
+        .any(|dir| is_within(dir, &target));
+
+    if hits_protected {
+        return Err(unsafe_path());
+    }
+
+    Ok(())
+}
+
+fn is_launcher_artifact(filename: &str, game_files: &HashSet<String>) -> bool {
+    let lower = filename.to_lowercase();
+
+    if lower.starts_with(&format!("{}/", STAGING_DIR_NAME))
+        || lower.starts_with(&INTEGRITY_DB_FILE_NAME.to_lowercase())
+    {
+        return true;
+    }
+
+    if LAUNCHER_ARTIFACTS
+        .iter()
+        .any(|artifact| lower.starts_with(&artifact.to_lowercase()))
+    {
+        return true;
+    }
+
+    // Temp files of interrupted downloads sit next to the file they were building
+    lower
+        .strip_suffix(".tmp")
+        .is_some_and(|built| game_files.contains(&normalize_install_dir(built)))
+}
+
+/// Works out which files an uninstall removes: everything the installed build lists, plus launcher artifacts. When that
+/// build's manifest isn't cached, every cached build counts instead.
+pub async fn plan_uninstall(install_dir: &Path) -> Result<UninstallPlan, DownloadError> {
+    check_uninstall_dir(install_dir)?;
+
+    // Other installs may be on builds with files this one never had
+    let installed_manifest = match get_object_by_location(&install_dir.to_string_lossy()).await {
+        Ok(installed) => find_cached_manifest(&installed.app_version).await.ok(),
+        Err(_) => None,
+    };
+    let manifests = match installed_manifest {
+        Some(manifest) => vec![manifest],
+        None => get_cached_manifests().await?,
+    };
+    if manifests.is_empty() {
+        // Without a manifest there is no telling game files from anything else in the folder
+        return Err(ManifestError::NoManifestFound.into());
+    }
+
+    let game_files: HashSet<String> = manifests
+        .iter()
+        .flat_map(|(_, manifest)| manifest.file_manifest_list.elements.iter())
+        .map(|fm| normalize_install_dir(&fm.filename))
+        .collect();
+
+    let on_disk = match install_dir.exists() {
+        true => walk_files(install_dir)?,
+        false => Vec::new(),
+    };
+
+    let mut files = Vec::new();
+    let mut kept = Vec::new();
+    let mut total_bytes = 0u64;
+
+    for filename in on_disk {
+        if game_files.contains(&normalize_install_dir(&filename)) || is_launcher_artifact(&filename, &game_files) {
+            let size = std::fs::metadata(install_dir.join(&filename))
+                .map(|m| m.len())
+                .unwrap_or(0);
+            total_bytes += size;
+            files.push(PlannedFile { filename, size });
+        } else {
+            kept.push(filename);
+        }
+    }
+
+    Ok(UninstallPlan {
+        install_dir: install_dir.to_string_lossy().to_string(),
+        files,
+        total_bytes,
+        kept,
+    })
+}
@@ -3,16 +3,24 @@ use sha1::{Digest, Sha1};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
-use std::{collections::HashMap, path::PathBuf};
-use tokio::fs::{self, File};
-use tokio::io::{AsyncWriteExt};
+use std::collections::hash_map::Entry;
+use std::{collections::HashMap, path::{Path, PathBuf}};
+use std::io::SeekFrom;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 use tokio::sync::{mpsc, Semaphore, Mutex};
 
 use crate::manifest::chunk_data::ChunkInfo;
-use crate::manifest::downloader::download_utils::{download_chunk_from_r2_streaming, guid_to_u128};
+use crate::manifest::downloader::download_utils::{chunk_key, download_chunk_from_r2_streaming, guid_to_u128};
 use crate::manifest::downloader::errors::DownloadError;
+use crate::config::get_hash_policy;
+use crate::manifest::downloader::hashing::{file_matches, HashPolicy};
+use crate::manifest::downloader::integrity::{hash_parts, IntegrityDb, VerifyMode};
+use crate::manifest::downloader::store::unshare_file;
 use crate::manifest::downloader::progress_update::ProgressUpdate; // same struct as downloader/installer
+use crate::manifest::errors::ChunkLoadError;
 use crate::manifest::ParsedManifest;
+use crate::retry::{circuit_breaker_for, RetryPolicy, Retryable};
 use crate::DownloadControl;
 
 // === Parallel verifier that mirrors downloader progress semantics ===
@@ -25,6 +33,7 @@ pub async fn verify_and_repair_parallel(
     install_dir: PathBuf,
     tx: mpsc::Sender<ProgressUpdate>,
     control: Arc<DownloadControl>,
+    mode: VerifyMode,
 ) -> Result<(), DownloadError> {
     // Build chunk map once
     let chunk_map: Arc<HashMap<u128, ChunkInfo>> = Arc::new(
@@ -46,7 +55,12 @@ pub async fn verify_and_repair_parallel(
         .sum();
     let downloaded_bytes = Arc::new(Mutex::new(0u64));
 
-    println!("Starting verification: {} files, {} total bytes", total_files, total_bytes);
+    println!("Starting {:?} verification: {} files, {} total bytes", mode, total_files, total_bytes);
+
+    let integrity = Arc::new(Mutex::new(IntegrityDb::load(&install_dir).await));
+    let hash_policy = get_hash_policy();
+
+    circuit_breaker_for(&bucket).reset();
 
     // Concurrency similar to downloader (adjust if you want them identical)
     let semaphore = Arc::new(Semaphore::new(10));
@@ -64,12 +78,14 @@ pub async fn verify_and_repair_parallel(
         let tx = tx.clone();
         let control = control.clone();
         let bucket = bucket.clone();
+        let install_dir = install_dir.clone();
+        let integrity = integrity.clone();
         let downloaded_bytes = downloaded_bytes.clone();
 
         tasks.push(tokio::spawn(async move {
             let _permit = permit;
 
-            const MAX_FILE_RETRIES: u32 = 3;
+            let policy = RetryPolicy::FILE;
             let mut file_attempt = 0;
 
             loop {
@@ -85,6 +101,10 @@ pub async fn verify_and_repair_parallel(
                     &tx,
                     &control,
                     &bucket,
+                    &install_dir,
+                    &integrity,
+                    mode,
+                    hash_policy,
                     total_files,
                     total_bytes,
                     downloaded_bytes.clone(),
@@ -94,16 +114,17 @@ pub async fn verify_and_repair_parallel(
                     Err(DownloadError::Cancelled) => return Err(DownloadError::Cancelled),
                     Err(e) => {
                         file_attempt += 1;
-                        if file_attempt > MAX_FILE_RETRIES {
+                        if !e.is_retryable() || file_attempt > policy.max_retries {
                             let _ = fs::remove_file(&tmp_path).await;
                             return Err(e);
                         }
+                        let backoff = policy.backoff(file_attempt);
                         eprintln!(
-                            "File verification/repair attempt {} failed for {}: {}. Retrying...",
-                            file_attempt, fm.filename, e
+                            "File verification/repair attempt {} failed for {}: {}. Retrying in {:?}...",
+                            file_attempt, fm.filename, e, backoff
                         );
                         let _ = fs::remove_file(&tmp_path).await;
-                        tokio::time::sleep(Duration::from_millis(1000 * file_attempt as u64)).await;
+                        tokio::time::sleep(backoff).await;
                     }
                 }
             }
@@ -127,6 +148,11 @@ pub async fn verify_and_repair_parallel(
     
     println!("Verification complete: {}/{} bytes processed", final_downloaded, total_bytes);
 
+    // Keep what was verified even if some files failed, so the next quick verify only redoes those
+    if let Err(e) = integrity.lock().await.save(&install_dir).await {
+        eprintln!("Couldn't save the integrity database: {}", e);
+    }
+
     // Determine the result but always send final tick
     let result = if !failed_files.is_empty() {
         Err(DownloadError::Multiple(failed_files))
@@ -175,20 +201,45 @@ async fn verify_and_repair_file_attempt(
     tx: &mpsc::Sender<ProgressUpdate>,
     control: &Arc<DownloadControl>,
     bucket: &str,
+    install_dir: &Path,
+    integrity: &Arc<Mutex<IntegrityDb>>,
+    mode: VerifyMode,
+    hash_policy: HashPolicy,
     total_files: usize,
     total_bytes: u64,
     downloaded_bytes: Arc<Mutex<u64>>,
 ) -> Result<(), DownloadError> {
+    // Quick verify trusts files that haven't been touched since they last matched their hash
+    let unchanged = mode == VerifyMode::Quick
+        && integrity
+            .lock()
+            .await
+            .is_unchanged(install_dir, &fm.filename, &fm.hash)
+            .await;
+
     // If the existing file is already valid, count its bytes toward global progress and return.
-    if out_path.exists() {
-        let existing = fs::read(&out_path).await?;
-        let mut hasher = Sha1::new();
-        hasher.update(&existing);
-        let hash = hasher.finalize();
-        if hash.as_slice() == fm.hash.as_slice() {
+    let mut existing_data = None;
+    if unchanged || out_path.exists() {
+        let valid_size = if unchanged {
+            Some(fm.file_size)
+        } else {
+            let existing = fs::read(&out_path).await?;
+            let valid_size = file_matches(fm, &existing, hash_policy).then_some(existing.len() as u64);
+            existing_data = Some(existing);
+            valid_size
+        };
+
+        if let Some(actual_size) = valid_size {
+            if let Some(existing) = &existing_data {
+                let _ = integrity
+                    .lock()
+                    .await
+                    .record(install_dir, &fm.filename, &fm.hash, hash_parts(fm, existing))
+                    .await;
+            }
+
             // CRITICAL FIX: Add the ACTUAL file size, not the file size from manifest
             // This ensures consistency between valid files and repaired files
-            let actual_size = existing.len() as u64;
             let mut global = downloaded_bytes.lock().await;
             *global += actual_size;
             let current_progress = *global;
@@ -214,7 +265,46 @@ async fn verify_and_repair_file_attempt(
         }
     }
 
+    // A file of the right size usually only has a few bad ranges, so only those are downloaded and patched in place
+    if let Some(existing) = existing_data.filter(|data| data.len() as u64 == fm.file_size) {
+        let recorded_parts = integrity
+            .lock()
+            .await
+            .part_hashes(&fm.filename, &fm.hash)
+            .map(|parts| parts.to_vec());
+
+        match repair_bad_parts(fm, out_path, existing, recorded_parts.as_deref(), chunk_map, control, bucket, hash_policy).await {
+            Ok(Some(part_hashes)) => {
+                let _ = integrity
+                    .lock()
+                    .await
+                    .record(install_dir, &fm.filename, &fm.hash, part_hashes)
+                    .await;
+
+                let mut global = downloaded_bytes.lock().await;
+                *global += fm.file_size;
+                let current_progress = *global;
+                drop(global);
+
+                let _ = tx
+                    .send(ProgressUpdate {
+                        filename: fm.filename.clone(),
+                        downloaded_bytes: current_progress,
+                        total_bytes,
+                        total_files,
+                    })
+                    .await;
+
+                return Ok(());
+            }
+            Ok(None) => eprintln!("Patching {} didn't fix it, rebuilding the whole file", fm.filename),
+            Err(DownloadError::Cancelled) => return Err(DownloadError::Cancelled),
+            Err(e) => eprintln!("Patching {} failed: {}. Rebuilding the whole file", fm.filename, e),
+        }
+    }
+
     // Otherwise, repair into a temporary path
+    integrity.lock().await.forget(&fm.filename);
     if let Some(parent) = tmp_path.parent() { fs::create_dir_all(parent).await?; }
     let mut file = File::create(&tmp_path).await?;
     let mut file_bytes_written = 0u64; // Track bytes for this specific file
@@ -226,19 +316,10 @@ async fn verify_and_repair_file_attempt(
         let chunk = chunk_map
             .get(&guid)
             .ok_or_else(|| DownloadError::ChunkMissing)?;
-        let key = format!(
-            "ChunksV4/{:02}/{:016X}_{}.chunk",
-            chunk.group_num,
-            chunk.hash,
-            chunk
-                .guid
-                .iter()
-                .map(|g| format!("{:08X}", g))
-                .collect::<String>()
-        );
+        let key = chunk_key(chunk);
 
         // Download the chunk (with simple retry) and write the requested slice to the tmp file
-        let chunk_data = download_chunk_with_retry(&bucket, &key).await?;
+        let chunk_data = download_chunk(&bucket, &key, control).await?;
         let start = cp.offset as usize;
         let end = start + cp.size as usize;
         if end > chunk_data.len() {
@@ -277,10 +358,7 @@ async fn verify_and_repair_file_attempt(
 
     // Verify final file hash
     let final_data = fs::read(&tmp_path).await?;
-    let mut hasher = Sha1::new();
-    hasher.update(&final_data);
-    let final_hash = hasher.finalize();
-    if final_hash.as_slice() != fm.hash.as_slice() {
+    if !file_matches(fm, &final_data, hash_policy) {
         return Err(DownloadError::RepairFailed(fm.filename.clone()));
     }
 
@@ -294,36 +372,142 @@ async fn verify_and_repair_file_attempt(
     }
 
     fs::rename(&tmp_path, &out_path).await?;
+    let _ = integrity
+        .lock()
+        .await
+        .record(install_dir, &fm.filename, &fm.hash, hash_parts(fm, &final_data))
+        .await;
     println!("Repaired file: {} ({} bytes)", fm.filename, actual_file_size);
     Ok(())
 }
 
-// Simple retry wrapper for chunk downloads
-async fn download_chunk_with_retry(bucket: &str, key: &str) -> Result<Vec<u8>, DownloadError> {
-    const MAX_RETRIES: u32 = 3;
-    const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
-    let mut attempt = 0;
-
-    loop {
-        match download_chunk_from_r2_streaming(bucket, key).await {
-            Ok(data) => return Ok(data),
-            Err(e) => {
-                attempt += 1;
-                if attempt > MAX_RETRIES {
-                    return Err(DownloadError::ChunkDownloadFailed(
-                        key.to_string(),
-                        format!("{}", e),
-                    ));
-                }
-                let backoff = INITIAL_BACKOFF * (2_u32.pow(attempt - 1));
-                eprintln!(
-                    "Chunk download attempt {} failed for {}: {}. Retrying in {:?}...",
-                    attempt, key, e, backoff
-                );
-                tokio::time::sleep(backoff).await;
+/// Indices of the chunk parts whose bytes don't match what they should contain. Parts are checked against the hashes
+/// recorded when the file was last known good, or against the chunk's own SHA1 when the part spans the whole chunk.
+/// Parts that can't be checked either way are treated as bad.
+fn find_bad_parts(
+    fm: &crate::manifest::file_manifest::FileManifest,
+    data: &[u8],
+    recorded_parts: Option<&[String]>,
+    chunk_map: &HashMap<u128, ChunkInfo>,
+) -> Vec<usize> {
+    let actual = hash_parts(fm, data);
+    let recorded_parts = recorded_parts.filter(|parts| parts.len() == fm.chunk_parts.len());
+    let mut offset = 0usize;
+
+    fm.chunk_parts
+        .iter()
+        .enumerate()
+        .filter_map(|(i, cp)| {
+            let range = offset..offset + cp.size as usize;
+            offset = range.end;
+
+            let valid = match recorded_parts {
+                Some(parts) => parts[i] == actual[i],
+                None => chunk_map.get(&guid_to_u128(&cp.guid)).is_some_and(|chunk| {
+                    cp.offset == 0
+                        && cp.size == chunk.window_size
+                        && !chunk.sha_hash.is_empty()
+                        && Sha1::digest(&data[range]).as_slice() == chunk.sha_hash.as_slice()
+                }),
+            };
+
+            (!valid).then_some(i)
+        })
+        .collect()
+}
+
+/// Downloads only the bad chunk parts of a file and writes them over the existing bytes.
+/// Returns the part hashes of the patched file, or nothing if it still doesn't match its hash.
+async fn repair_bad_parts(
+    fm: &crate::manifest::file_manifest::FileManifest,
+    out_path: &PathBuf,
+    data: Vec<u8>,
+    recorded_parts: Option<&[String]>,
+    chunk_map: &HashMap<u128, ChunkInfo>,
+    control: &Arc<DownloadControl>,
+    bucket: &str,
+    hash_policy: HashPolicy,
+) -> Result<Option<Vec<String>>, DownloadError> {
+    let bad_parts = find_bad_parts(fm, &data, recorded_parts, chunk_map);
+    drop(data);
+    let part_offsets: Vec<usize> = fm
+        .chunk_parts
+        .iter()
+        .scan(0usize, |offset, cp| {
+            let start = *offset;
+            *offset += cp.size as usize;
+            Some(start)
+        })
+        .collect();
+
+    println!(
+        "Patching {} of {} chunk parts in {}",
+        bad_parts.len(),
+        fm.chunk_parts.len(),
+        fm.filename
+    );
+
+    // Writing in place would also change every install linked to the same stored file
+    unshare_file(out_path).await?;
+    let mut file = OpenOptions::new().write(true).open(out_path).await?;
+    let mut downloaded: HashMap<u128, Vec<u8>> = HashMap::new();
+
+    for i in bad_parts {
+        if control.cancelled.load(Ordering::Relaxed) {
+            return Err(DownloadError::Cancelled);
+        }
+
+        let cp = &fm.chunk_parts[i];
+        let guid = guid_to_u128(&cp.guid);
+
+        // Several bad parts of one file often come from the same chunk
+        let chunk_data = match downloaded.entry(guid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let chunk = chunk_map.get(&guid).ok_or(DownloadError::ChunkMissing)?;
+                entry.insert(download_chunk(bucket, &chunk_key(chunk), control).await?)
             }
+        };
+
+        let start = cp.offset as usize;
+        let end = start + cp.size as usize;
+        if end > chunk_data.len() {
+            return Err(DownloadError::ChunkCorrupt(format!(
+                "Chunk part out of bounds in {} (chunk len = {}, start = {}, end = {})",
+                fm.filename,
+                chunk_data.len(),
+                start,
+                end
+            )));
         }
+
+        let file_offset = part_offsets[i];
+        file.seek(SeekFrom::Start(file_offset as u64)).await?;
+        file.write_all(&chunk_data[start..end]).await?;
     }
+
+    file.flush().await?;
+    file.sync_data().await?;
+    drop(file);
+
+    let patched = fs::read(out_path).await?;
+    if !file_matches(fm, &patched, hash_policy) {
+        return Ok(None);
+    }
+
+    Ok(Some(hash_parts(fm, &patched)))
+}
+
+// Transfer errors and corrupt chunks are already retried inside the streaming download
+async fn download_chunk(bucket: &str, key: &str, control: &DownloadControl) -> Result<Vec<u8>, DownloadError> {
+    let data = download_chunk_from_r2_streaming(bucket, key)
+        .await
+        .map_err(|e| match e {
+            ChunkLoadError::SourceUnavailable(outage) => DownloadError::SourceUnavailable(outage),
+            e => DownloadError::ChunkDownloadFailed(key.to_string(), e.to_string()),
+        })?;
+    control.fetched_bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+    Ok(data)
 }
 
 // === Optional sequential variant updated to mirror downloader progress ===
@@ -352,6 +536,7 @@ pub async fn verify_and_repair_sequential(
 
     println!("Starting sequential verification: {} files, {} total bytes", total_files, total_bytes);
 
+    let hash_policy = get_hash_policy();
     let mut overall_success = true;
     let mut last_error = None;
 
@@ -361,11 +546,11 @@ pub async fn verify_and_repair_sequential(
         let out_path = install_dir.join(&fm.filename);
         let tmp_path = install_dir.join(format!("{}.tmp", &fm.filename));
 
+        let policy = RetryPolicy::FILE;
         let mut file_attempt = 0;
-        const MAX_FILE_RETRIES: u32 = 3;
         let mut success = false;
 
-        while file_attempt < MAX_FILE_RETRIES && !success {
+        while file_attempt <= policy.max_retries && !success {
             file_attempt += 1;
 
             match repair_file_sequential(
@@ -379,6 +564,7 @@ pub async fn verify_and_repair_sequential(
                 total_files,
                 total_bytes,
                 downloaded_bytes.clone(),
+                hash_policy,
             ).await {
                 Ok(()) => {
                     success = true;
@@ -387,9 +573,13 @@ pub async fn verify_and_repair_sequential(
                 Err(e) => {
                     eprintln!("Sequential repair attempt {} failed for {}: {}", file_attempt, fm.filename, e);
                     let _ = fs::remove_file(&tmp_path).await;
+                    if !e.is_retryable() {
+                        last_error = Some(e);
+                        break;
+                    }
                     last_error = Some(e);
-                    if file_attempt < MAX_FILE_RETRIES {
-                        tokio::time::sleep(Duration::from_millis(1000 * file_attempt as u64)).await;
+                    if file_attempt <= policy.max_retries {
+                        tokio::time::sleep(policy.backoff(file_attempt)).await;
                     }
                 }
             }
@@ -457,14 +647,12 @@ async fn repair_file_sequential(
     total_files: usize,
     total_bytes: u64,
     downloaded_bytes: Arc<Mutex<u64>>,
+    hash_policy: HashPolicy,
 ) -> Result<(), DownloadError> {
     // If the existing file is already valid, count its bytes and return
     if out_path.exists() {
         let existing = fs::read(&out_path).await?;
-        let mut hasher = Sha1::new();
-        hasher.update(&existing);
-        let hash = hasher.finalize();
-        if hash.as_slice() == fm.hash.as_slice() {
+        if file_matches(fm, &existing, hash_policy) {
             // Use actual file size for consistency
             let actual_size = existing.len() as u64;
             let mut global = downloaded_bytes.lock().await;
@@ -493,17 +681,8 @@ async fn repair_file_sequential(
         let chunk = chunk_map
             .get(&guid)
             .ok_or_else(|| DownloadError::ChunkMissing)?;
-        let key = format!(
-            "ChunksV4/{:02}/{:016X}_{}.chunk",
-            chunk.group_num,
-            chunk.hash,
-            chunk
-                .guid
-                .iter()
-                .map(|g| format!("{:08X}", g))
-                .collect::<String>()
-        );
-        let chunk_data = download_chunk_with_retry(&bucket, &key).await?;
+        let key = chunk_key(chunk);
+        let chunk_data = download_chunk(&bucket, &key, control).await?;
         let start = cp.offset as usize;
         let end = start + cp.size as usize;
         if end > chunk_data.len() {
@@ -535,12 +714,75 @@ async fn repair_file_sequential(
 
     // Verify and finalize
     let final_data = fs::read(&tmp_path).await?;
-    let mut hasher = Sha1::new();
-    hasher.update(&final_data);
-    let final_hash = hasher.finalize();
-    if final_hash.as_slice() != fm.hash.as_slice() {
+    if !file_matches(fm, &final_data, hash_policy) {
         return Err(DownloadError::RepairFailed(fm.filename.clone()));
     }
     fs::rename(&tmp_path, &out_path).await?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::file_manifest::{ChunkPart, FileManifest};
+
+    fn chunk(id: u32, data: &[u8]) -> ChunkInfo {
+        ChunkInfo {
+            guid: [0, 0, 0, id],
+            hash: 0,
+            sha_hash: Sha1::digest(data).to_vec(),
+            group_num: 0,
+            window_size: data.len() as u32,
+            file_size: data.len() as i64,
+        }
+    }
+
+    fn part(id: u32, offset: u32, size: u32) -> ChunkPart {
+        ChunkPart { guid: [0, 0, 0, id], offset, size, file_offset: 0 }
+    }
+
+    /// A file made of all of chunk 1 followed by the middle of chunk 2, which is "abcd" + "yz"
+    fn setup() -> (FileManifest, HashMap<u128, ChunkInfo>) {
+        let chunks = [chunk(1, b"abcd"), chunk(2, b"xyzw")];
+        let fm = FileManifest {
+            filename: "Game.pak".to_string(),
+            symlink_target: String::new(),
+            hash: Vec::new(),
+            flags: 0,
+            install_tags: Vec::new(),
+            chunk_parts: vec![part(1, 0, 4), part(2, 1, 2)],
+            file_size: 6,
+            hash_md5: None,
+            mime_type: None,
+            hash_sha256: None,
+        };
+
+        (fm, chunks.into_iter().map(|c| (guid_to_u128(&c.guid), c)).collect())
+    }
+
+    #[test]
+    fn recorded_part_hashes_find_exactly_the_damaged_parts() {
+        let (fm, chunk_map) = setup();
+        let recorded = hash_parts(&fm, b"abcdyz");
+
+        assert!(find_bad_parts(&fm, b"abcdyz", Some(&recorded), &chunk_map).is_empty());
+        assert_eq!(find_bad_parts(&fm, b"abcdy!", Some(&recorded), &chunk_map), vec![1]);
+        assert_eq!(find_bad_parts(&fm, b"!bcdyz", Some(&recorded), &chunk_map), vec![0]);
+    }
+
+    #[test]
+    fn without_recorded_hashes_only_whole_chunks_can_be_trusted() {
+        let (fm, chunk_map) = setup();
+
+        assert_eq!(find_bad_parts(&fm, b"abcdyz", None, &chunk_map), vec![1]);
+        assert_eq!(find_bad_parts(&fm, b"!bcdyz", None, &chunk_map), vec![0, 1]);
+    }
+
+    #[test]
+    fn recorded_hashes_for_another_layout_are_ignored() {
+        let (fm, chunk_map) = setup();
+        let stale = vec![String::new()];
+
+        assert_eq!(find_bad_parts(&fm, b"abcdyz", Some(&stale), &chunk_map), vec![1]);
+    }
+}
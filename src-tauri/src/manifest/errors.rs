@@ -3,7 +3,9 @@ use thiserror::Error;
 use aws_sdk_s3::operation::get_object::GetObjectError;
 use aws_smithy_runtime_api::client::result::SdkError;
 
+use crate::auth::AuthError;
 use crate::config::ConfigError;
+use crate::retry::{CircuitOpen, Retryable};
 
 #[derive(Error, Debug)]
 pub enum ManifestError {
@@ -22,6 +24,12 @@ pub enum ManifestError {
     #[error("There was only one manifest in the cache")]
     NoSecondLatestManifestFound,
 
+    #[error("Build {0} is not in the manifest cache")]
+    BuildNotCached(String),
+
+    #[error("The update has not finished applying yet")]
+    UpdateNotCommitted,
+
     #[error("{0}")]
     AuthenticationFailed(String),
 
@@ -31,6 +39,9 @@ pub enum ManifestError {
     #[error("Network error: {0}")]
     NetworkError(#[from] reqwest::Error),
 
+    #[error("{0}")]
+    AuthError(#[from] AuthError),
+
     #[error("Config error: {0}")]
     ConfigError(#[from] ConfigError),
 
@@ -84,6 +95,9 @@ pub enum ChunkLoadError {
 
     #[error("AWS error: {0}")]
     AWSError(#[from] SdkError<GetObjectError, aws_smithy_runtime_api::http::Response>),
+
+    #[error("{0}")]
+    SourceUnavailable(#[from] CircuitOpen),
 }
 
 impl serde::Serialize for ChunkLoadError {
@@ -94,3 +108,23 @@ impl serde::Serialize for ChunkLoadError {
         serializer.serialize_str(&self.to_string())
     }
 }
+
+impl Retryable for ChunkLoadError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            // A truncated or garbled transfer usually comes through fine the next time
+            ChunkLoadError::IncorrectFileSize
+            | ChunkLoadError::DecompressFailure
+            | ChunkLoadError::HashCheckFailed
+            | ChunkLoadError::DownloadFailed(_, _)
+            | ChunkLoadError::IoError(_)
+            | ChunkLoadError::AWSError(_) => true,
+            ChunkLoadError::InvalidMagic
+            | ChunkLoadError::UnknownVersion(_)
+            | ChunkLoadError::UnsupportedStorage
+            | ChunkLoadError::MissingHashInfo
+            | ChunkLoadError::SerializationError
+            | ChunkLoadError::SourceUnavailable(_) => false,
+        }
+    }
+}
@@ -8,20 +8,20 @@ pub use crate::manifest::file_manifest::FileManifestList;
 
 use crate::manifest::manifest_utils::read_fstring;
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct ManifestMeta {
-    app_id: u32,
-    app_name: String,
-    build_version: String,
-    launch_exe: String,
-    launch_command: String,
-    prereq_ids: Vec<String>,
-    prereq_name: String,
-    prereq_path: String,
-    prereq_args: String,
-    build_id: String,
-    uninstall_action_path: String,
-    uninstall_action_args: String,
+    pub app_id: u32,
+    pub app_name: String,
+    pub build_version: String,
+    pub launch_exe: String,
+    pub launch_command: String,
+    pub prereq_ids: Vec<String>,
+    pub prereq_name: String,
+    pub prereq_path: String,
+    pub prereq_args: String,
+    pub build_id: String,
+    pub uninstall_action_path: String,
+    pub uninstall_action_args: String,
 }
 
 #[derive(Serialize)]
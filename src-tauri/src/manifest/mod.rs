@@ -88,8 +88,8 @@ pub async fn mark_current_manifest_as_complete(installed_location: &String) -> R
     Ok(())
 }
 
-/// Marks the game as deleted and deletes the downloaded manifests.
-pub async fn mark_game_as_deleted() -> Result<(), ManifestError> {
-    downloader::mark_game_as_deleted().await?;
+/// Marks an install as deleted, deleting the downloaded manifests once no install is left.
+pub async fn mark_game_as_deleted(install_id: Option<&str>) -> Result<(), ManifestError> {
+    downloader::mark_game_as_deleted(install_id).await?;
     Ok(())
 }
\ No newline at end of file
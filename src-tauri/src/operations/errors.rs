@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum OperationError {
+    #[error("Operation {0} does not exist")]
+    NotFound(u64),
+
+    #[error("Operation {0} has already finished")]
+    AlreadyFinished(u64),
+
+    #[error("Operation {0} is not queued")]
+    NotQueued(u64),
+
+    #[error("This wasn't supposed to happen! Pleaase contact support!")]
+    UnexpectedError,
+}
+
+impl serde::Serialize for OperationError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::commands::DownloadControl;
+use crate::launcher::errors::LaunchError;
+use crate::launcher::game_launcher::ProcessUtils;
+use crate::manifest::downloader::errors::DownloadError;
+use crate::operations::operation_info::normalize_install_dir;
+use crate::operations::{OperationInfo, OperationKind, OperationManager};
+
+/// How often a job waiting for the game to exit looks again
+const GAME_EXIT_POLL: Duration = Duration::from_secs(2);
+
+/// What a job that changes game files does when it finds the game running
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameRunningPolicy {
+    /// Fail straight away
+    #[default]
+    Refuse,
+    /// Stay queued until the game exits
+    Wait,
+    /// Close the game, then go ahead
+    CloseGame,
+}
+
+fn describe(kind: OperationKind) -> &'static str {
+    match kind {
+        OperationKind::Install => "an install",
+        OperationKind::Update => "an update",
+        OperationKind::Verify => "a verify",
+        OperationKind::Repair => "a repair",
+        OperationKind::Move => "a move",
+        OperationKind::Uninstall => "an uninstall",
+    }
+}
+
+/// Makes sure the game isn't running from the install before a job touches its files, as the policy says. Copies
+/// started from other installs are left alone.
+pub async fn ensure_game_closed(
+    policy: GameRunningPolicy,
+    install_dir: &str,
+    control: &DownloadControl,
+) -> Result<(), DownloadError> {
+    loop {
+        if !ProcessUtils::is_game_running_from(install_dir) {
+            return Ok(());
+        }
+        if control.cancelled.load(Ordering::Relaxed) {
+            return Err(DownloadError::Cancelled);
+        }
+
+        match policy {
+            GameRunningPolicy::Refuse => return Err(DownloadError::GameRunning),
+            GameRunningPolicy::CloseGame => {
+                println!("Closing Fortnite so the files of {} can be changed", install_dir);
+                let dir = install_dir.to_string();
+                tokio::task::spawn_blocking(move || ProcessUtils::close_game_from(&dir))
+                    .await?
+                    .map_err(|_| DownloadError::GameRunning)?;
+
+                // Closing can fail silently, so the next pass checks again and gives up if it's still there
+                if ProcessUtils::is_game_running_from(install_dir) {
+                    return Err(DownloadError::GameRunning);
+                }
+            }
+            GameRunningPolicy::Wait => tokio::time::sleep(GAME_EXIT_POLL).await,
+        }
+    }
+}
+
+/// The queued or running job that would be disturbed by starting the game from this install
+pub fn blocking_operation(manager: &OperationManager, install_dir: &str) -> Option<OperationInfo> {
+    let install_dir = normalize_install_dir(install_dir);
+    manager
+        .list()
+        .into_iter()
+        .find(|info| info.kind == OperationKind::Move || normalize_install_dir(&info.install_dir) == install_dir)
+}
+
+/// Refuses to start the game while a job is changing its files
+pub fn ensure_can_launch(manager: &OperationManager, install_dir: &str) -> Result<(), LaunchError> {
+    match blocking_operation(manager, install_dir) {
+        Some(info) => Err(LaunchError::OperationInProgress(describe(info.kind).to_string())),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,493 @@
+pub mod errors;
+pub mod guard;
+pub mod operation_info;
+pub mod persist;
+pub mod recovery;
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::Notify;
+
+use crate::commands::DownloadControl;
+use crate::manifest::downloader::errors::DownloadError;
+use crate::manifest::downloader::progress_update::ProgressUpdate;
+
+pub use errors::OperationError;
+pub use guard::GameRunningPolicy;
+pub use operation_info::{OperationInfo, OperationKind, OperationStatus};
+
+/// How many finished operations are kept around for the history view
+const MAX_HISTORY: usize = 50;
+
+static OPERATION_MANAGER: OnceLock<Arc<OperationManager>> = OnceLock::new();
+
+/// Returns the process wide operation manager
+pub fn get_operation_manager() -> &'static Arc<OperationManager> {
+    OPERATION_MANAGER.get_or_init(|| Arc::new(OperationManager::new()))
+}
+
+struct OperationEntry {
+    info: OperationInfo,
+    control: Arc<DownloadControl>,
+}
+
+#[derive(Default)]
+struct OperationState {
+    active: Vec<OperationEntry>,
+    history: VecDeque<OperationInfo>,
+}
+
+enum Turn {
+    Started,
+    Waiting,
+    Cancelled,
+}
+
+impl OperationState {
+    fn try_start(&mut self, id: u64) -> Turn {
+        let Some(index) = self.active.iter().position(|e| e.info.id == id) else {
+            // Removed from the queue before it got to run
+            return Turn::Cancelled;
+        };
+
+        let entry = &self.active[index].info;
+        let blocked = self.active.iter().any(|other| {
+            other.info.id != id
+                && entry.conflicts_with(&other.info)
+                && match other.info.status {
+                    OperationStatus::Running => true,
+                    OperationStatus::Queued => other.info.ranks_before(entry),
+                    _ => false,
+                }
+        });
+
+        if blocked {
+            return Turn::Waiting;
+        }
+
+        let entry = &mut self.active[index].info;
+        entry.status = OperationStatus::Running;
+        entry.started_at = Some(unix_now());
+        Turn::Started
+    }
+
+    fn archive(&mut self, mut info: OperationInfo, status: OperationStatus, error: Option<String>) {
+        info.status = status;
+        info.error = error;
+        info.finished_at = Some(unix_now());
+
+        self.history.push_back(info);
+        while self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+    }
+}
+
+/// Marks an operation as cancelled if its future is dropped before it finishes
+struct RunningOperation<'a> {
+    manager: &'a OperationManager,
+    id: u64,
+    finished: bool,
+}
+
+impl Drop for RunningOperation<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.manager.finish(self.id, OperationStatus::Cancelled, None);
+        }
+    }
+}
+
+/// Tracks every install, update, verify, repair, move and uninstall job, queueing jobs that would touch the same install at once
+pub struct OperationManager {
+    state: Mutex<OperationState>,
+    notify: Notify,
+    next_id: AtomicU64,
+}
+
+impl OperationManager {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(OperationState::default()),
+            notify: Notify::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Queues an operation, waits until nothing conflicting is running, then runs it with its own control.
+    /// Doesn't look for a running game, so it suits jobs that only read the install or tolerate files in use.
+    pub async fn run<T, F, Fut>(
+        &self,
+        kind: OperationKind,
+        install_dir: String,
+        operation: F,
+    ) -> Result<T, DownloadError>
+    where
+        F: FnOnce(Arc<DownloadControl>) -> Fut,
+        Fut: Future<Output = Result<T, DownloadError>>,
+    {
+        self.run_inner(kind, install_dir, None, operation).await
+    }
+
+    /// Like `run`, for jobs that change game files, dealing with the game running from the install as the policy says
+    pub async fn run_guarded<T, F, Fut>(
+        &self,
+        kind: OperationKind,
+        install_dir: String,
+        if_game_running: GameRunningPolicy,
+        operation: F,
+    ) -> Result<T, DownloadError>
+    where
+        F: FnOnce(Arc<DownloadControl>) -> Fut,
+        Fut: Future<Output = Result<T, DownloadError>>,
+    {
+        self.run_inner(kind, install_dir, Some(if_game_running), operation)
+            .await
+    }
+
+    async fn run_inner<T, F, Fut>(
+        &self,
+        kind: OperationKind,
+        install_dir: String,
+        if_game_running: Option<GameRunningPolicy>,
+        operation: F,
+    ) -> Result<T, DownloadError>
+    where
+        F: FnOnce(Arc<DownloadControl>) -> Fut,
+        Fut: Future<Output = Result<T, DownloadError>>,
+    {
+        let (id, control) = self.enqueue(kind, install_dir.clone());
+        let mut running = RunningOperation {
+            manager: self,
+            id,
+            finished: false,
+        };
+
+        if let Some(policy) = if_game_running {
+            // Checked while still queued, so a refused or cancelled wait never shows up as having started
+            if let Err(e) = guard::ensure_game_closed(policy, &install_dir, &control).await {
+                running.finished = true;
+                self.finish_failed(id, &e);
+                return Err(e);
+            }
+        }
+
+        self.wait_for_turn(id).await?;
+
+        if let Some(policy) = if_game_running {
+            // The game may have been started while the job was queued behind another one
+            if let Err(e) = guard::ensure_game_closed(policy, &install_dir, &control).await {
+                running.finished = true;
+                self.finish_failed(id, &e);
+                return Err(e);
+            }
+        }
+
+        let result = operation(control).await;
+        running.finished = true;
+
+        match &result {
+            Ok(_) => self.finish(id, OperationStatus::Completed, None),
+            Err(e) => self.finish_failed(id, e),
+        }
+
+        result
+    }
+
+    /// Archives an operation that stopped with an error, as cancelled if that's why it stopped
+    fn finish_failed(&self, id: u64, error: &DownloadError) {
+        match error {
+            DownloadError::Cancelled => self.finish(id, OperationStatus::Cancelled, None),
+            e => self.finish(id, OperationStatus::Failed, Some(e.to_string())),
+        }
+    }
+
+    fn enqueue(&self, kind: OperationKind, install_dir: String) -> (u64, Arc<DownloadControl>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let control = Arc::new(DownloadControl::default());
+
+        let mut state = self.state.lock().unwrap();
+        state.active.push(OperationEntry {
+            info: OperationInfo {
+                id,
+                kind,
+                install_dir,
+                status: OperationStatus::Queued,
+                priority: 0,
+                queued_at: unix_now(),
+                started_at: None,
+                finished_at: None,
+                error: None,
+            },
+            control: control.clone(),
+        });
+
+        (id, control)
+    }
+
+    async fn wait_for_turn(&self, id: u64) -> Result<(), DownloadError> {
+        loop {
+            // Register for wakeups before checking so a finish in between isn't missed
+            let notified = self.notify.notified();
+
+            let turn = self.state.lock().unwrap().try_start(id);
+            match turn {
+                Turn::Started => {
+                    self.persist_started(id);
+                    return Ok(());
+                }
+                Turn::Cancelled => return Err(DownloadError::Cancelled),
+                Turn::Waiting => {}
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Records a started operation on disk, so a crash partway through can be recovered from on the next start
+    fn persist_started(&self, id: u64) {
+        let info = self
+            .state
+            .lock()
+            .unwrap()
+            .active
+            .iter()
+            .find(|e| e.info.id == id)
+            .map(|e| e.info.clone());
+
+        if let Some(info) = info {
+            persist::record_started(&info);
+        }
+    }
+
+    fn finish(&self, id: u64, status: OperationStatus, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        let mut finished = None;
+        if let Some(index) = state.active.iter().position(|e| e.info.id == id) {
+            let entry = state.active.remove(index);
+            finished = Some((entry.info.clone(), entry.info.started_at.is_some()));
+            state.archive(entry.info, status, error);
+        }
+        drop(state);
+
+        // Operations cancelled while queued never made it to disk
+        if let Some((info, started)) = finished {
+            if started {
+                persist::record_finished(&info, status == OperationStatus::Completed);
+            }
+        }
+
+        self.notify.notify_waiters();
+    }
+
+    /// Cancels a queued or running operation
+    pub fn cancel(&self, id: u64) -> Result<(), OperationError> {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(index) = state.active.iter().position(|e| e.info.id == id) else {
+            return match state.history.iter().any(|info| info.id == id) {
+                true => Err(OperationError::AlreadyFinished(id)),
+                false => Err(OperationError::NotFound(id)),
+            };
+        };
+
+        state.active[index].control.cancelled.store(true, Ordering::Relaxed);
+
+        // Queued operations never started, so they can be archived straight away
+        if state.active[index].info.status == OperationStatus::Queued {
+            let entry = state.active.remove(index);
+            state.archive(entry.info, OperationStatus::Cancelled, None);
+            drop(state);
+            self.notify.notify_waiters();
+        }
+
+        Ok(())
+    }
+
+    /// Cancels every queued or running operation on an install directory, returning the ids of the cancelled ones
+    pub fn cancel_install_dir(&self, install_dir: &str) -> Vec<u64> {
+        let install_dir = operation_info::normalize_install_dir(install_dir);
+        let ids: Vec<u64> = self
+            .list()
+            .into_iter()
+            .filter(|info| operation_info::normalize_install_dir(&info.install_dir) == install_dir)
+            .map(|info| info.id)
+            .collect();
+
+        ids.into_iter().filter(|id| self.cancel(*id).is_ok()).collect()
+    }
+
+    /// Waits until none of the given operations are queued or running any more
+    pub async fn wait_for(&self, ids: &[u64]) {
+        loop {
+            // Register for wakeups before checking so a finish in between isn't missed
+            let notified = self.notify.notified();
+
+            let pending = self
+                .state
+                .lock()
+                .unwrap()
+                .active
+                .iter()
+                .any(|e| ids.contains(&e.info.id));
+            if !pending {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Changes the priority of a queued operation. Higher priorities start first.
+    pub fn reprioritize(&self, id: u64, priority: i32) -> Result<(), OperationError> {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(entry) = state.active.iter_mut().find(|e| e.info.id == id) else {
+            return match state.history.iter().any(|info| info.id == id) {
+                true => Err(OperationError::AlreadyFinished(id)),
+                false => Err(OperationError::NotFound(id)),
+            };
+        };
+
+        if entry.info.status != OperationStatus::Queued {
+            return Err(OperationError::NotQueued(id));
+        }
+
+        entry.info.priority = priority;
+        drop(state);
+
+        self.notify.notify_waiters();
+        Ok(())
+    }
+
+    /// Returns the queued and running operations
+    pub fn list(&self) -> Vec<OperationInfo> {
+        let state = self.state.lock().unwrap();
+        state.active.iter().map(|e| e.info.clone()).collect()
+    }
+
+    /// Returns finished operations, newest first
+    pub fn history(&self) -> Vec<OperationInfo> {
+        let state = self.state.lock().unwrap();
+        state.history.iter().rev().cloned().collect()
+    }
+
+    /// Returns the progress of an operation
+    pub async fn progress(&self, id: u64) -> Result<Vec<ProgressUpdate>, OperationError> {
+        let control = {
+            let state = self.state.lock().unwrap();
+            state
+                .active
+                .iter()
+                .find(|e| e.info.id == id)
+                .map(|e| e.control.clone())
+                .ok_or(OperationError::NotFound(id))?
+        };
+
+        let progress = control.progress.lock().await.clone();
+        Ok(progress)
+    }
+
+    /// Returns the progress of the first running operation, or nothing if everything is idle
+    pub async fn current_progress(&self) -> Vec<ProgressUpdate> {
+        let control = {
+            let state = self.state.lock().unwrap();
+            state
+                .active
+                .iter()
+                .find(|e| e.info.status == OperationStatus::Running)
+                .map(|e| e.control.clone())
+        };
+
+        match control {
+            Some(control) => control.progress.lock().await.clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Default for OperationManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u64, kind: OperationKind, install_dir: &str, priority: i32, status: OperationStatus) -> OperationEntry {
+        OperationEntry {
+            info: OperationInfo {
+                id,
+                kind,
+                install_dir: install_dir.to_string(),
+                status,
+                priority,
+                queued_at: 0,
+                started_at: None,
+                finished_at: None,
+                error: None,
+            },
+            control: Arc::new(DownloadControl::default()),
+        }
+    }
+
+    fn state(active: Vec<OperationEntry>) -> OperationState {
+        OperationState { active, history: VecDeque::new() }
+    }
+
+    #[test]
+    fn waits_for_a_running_operation_on_the_same_install() {
+        let mut state = state(vec![
+            entry(1, OperationKind::Update, "C:\\Games\\Reality", 0, OperationStatus::Running),
+            entry(2, OperationKind::Verify, "c:/games/reality/", 0, OperationStatus::Queued),
+            entry(3, OperationKind::Verify, "D:\\Other", 0, OperationStatus::Queued),
+        ]);
+
+        assert!(matches!(state.try_start(2), Turn::Waiting));
+        assert!(matches!(state.try_start(3), Turn::Started));
+        assert_eq!(state.active[2].info.status, OperationStatus::Running);
+        assert!(state.active[2].info.started_at.is_some());
+    }
+
+    #[test]
+    fn starts_queued_operations_in_rank_order() {
+        let mut state = state(vec![
+            entry(1, OperationKind::Verify, "C:\\Games\\Reality", 0, OperationStatus::Queued),
+            entry(2, OperationKind::Repair, "C:\\Games\\Reality", 5, OperationStatus::Queued),
+        ]);
+
+        assert!(matches!(state.try_start(1), Turn::Waiting));
+        assert!(matches!(state.try_start(2), Turn::Started));
+        assert!(matches!(state.try_start(1), Turn::Waiting));
+    }
+
+    #[test]
+    fn a_move_waits_for_every_install() {
+        let mut state = state(vec![
+            entry(1, OperationKind::Verify, "D:\\Other", 0, OperationStatus::Running),
+            entry(2, OperationKind::Move, "C:\\Games\\Reality", 0, OperationStatus::Queued),
+        ]);
+
+        assert!(matches!(state.try_start(2), Turn::Waiting));
+    }
+
+    #[test]
+    fn an_operation_gone_from_the_queue_is_cancelled() {
+        let mut state = state(vec![entry(1, OperationKind::Verify, "C:\\Games\\Reality", 0, OperationStatus::Queued)]);
+
+        assert!(matches!(state.try_start(2), Turn::Cancelled));
+    }
+}
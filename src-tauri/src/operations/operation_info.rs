@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationKind {
+    Install,
+    Update,
+    Verify,
+    Repair,
+    Move,
+    Uninstall,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OperationInfo {
+    pub id: u64,
+    pub kind: OperationKind,
+    pub install_dir: String,
+    pub status: OperationStatus,
+    pub priority: i32,
+    pub queued_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl OperationInfo {
+    /// Two operations conflict when they touch the same install directory. Moves touch two directories, so they conflict with everything.
+    pub fn conflicts_with(&self, other: &OperationInfo) -> bool {
+        if self.kind == OperationKind::Move || other.kind == OperationKind::Move {
+            return true;
+        }
+
+        normalize_install_dir(&self.install_dir) == normalize_install_dir(&other.install_dir)
+    }
+
+    /// Whether this operation should start before another queued operation (higher priority first, then oldest first)
+    pub fn ranks_before(&self, other: &OperationInfo) -> bool {
+        self.priority > other.priority || (self.priority == other.priority && self.id < other.id)
+    }
+}
+
+/// Windows paths are case insensitive and accept both separators, so compare them in a canonical form
+pub fn normalize_install_dir(install_dir: &str) -> String {
+    install_dir
+        .replace('/', "\\")
+        .trim_end_matches('\\')
+        .to_lowercase()
+}
+
+/// Whether a path is a folder or inside it, both normalized with `normalize_install_dir`
+pub fn is_within(path: &str, dir: &str) -> bool {
+    path == dir || path.starts_with(&format!("{}\\", dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(id: u64, priority: i32) -> OperationInfo {
+        OperationInfo {
+            id,
+            kind: OperationKind::Verify,
+            install_dir: "C:\\Games\\Reality".to_string(),
+            status: OperationStatus::Queued,
+            priority,
+            queued_at: 0,
+            started_at: None,
+            finished_at: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn higher_priority_ranks_first() {
+        assert!(queued(2, 5).ranks_before(&queued(1, 0)));
+        assert!(!queued(1, 0).ranks_before(&queued(2, 5)));
+    }
+
+    #[test]
+    fn equal_priority_ranks_oldest_first() {
+        assert!(queued(1, 0).ranks_before(&queued(2, 0)));
+        assert!(!queued(2, 0).ranks_before(&queued(1, 0)));
+        assert!(!queued(1, 0).ranks_before(&queued(1, 0)));
+    }
+}
@@ -0,0 +1,142 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::operations::operation_info::normalize_install_dir;
+use crate::operations::{OperationInfo, OperationKind};
+
+/// Serializes reads and writes of the state file between operations finishing at the same time
+static STATE_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// An operation that was running when the state was last written
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PersistedOperation {
+    pub id: u64,
+    pub kind: OperationKind,
+    pub install_dir: String,
+    pub started_at: u64,
+}
+
+/// What the UI should offer for an install whose operation never finished
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecoveryAction {
+    /// Run the install, update or uninstall again
+    Resume,
+    /// Verify and repair the install
+    Repair,
+}
+
+/// An operation found still running on startup, after the launcher cleaned up after it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InterruptedOperation {
+    pub kind: OperationKind,
+    pub install_dir: String,
+    pub started_at: u64,
+    pub action: RecoveryAction,
+    /// Whether a half applied update was rolled back to the previous build
+    pub rolled_back: bool,
+    /// Temp files of unfinished downloads that were removed
+    pub removed_temp_files: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PersistedState {
+    #[serde(default)]
+    pub running: Vec<PersistedOperation>,
+    #[serde(default)]
+    pub interrupted: Vec<InterruptedOperation>,
+}
+
+fn get_operation_state_path() -> io::Result<PathBuf> {
+    let local_app_data = std::env::var("LOCALAPPDATA").map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "LOCALAPPDATA environment variable not found",
+        )
+    })?;
+
+    Ok(PathBuf::from(local_app_data)
+        .join("RealityLauncher")
+        .join("Operations.json"))
+}
+
+fn read_state() -> PersistedState {
+    let Ok(path) = get_operation_state_path() else {
+        return PersistedState::default();
+    };
+
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            eprintln!("Ignoring unreadable operation state: {}", e);
+            PersistedState::default()
+        }),
+        Err(_) => PersistedState::default(),
+    }
+}
+
+/// Written to a temp file first, so a crash mid-write never loses what was already there
+fn write_state(state: &PersistedState) -> io::Result<()> {
+    let path = get_operation_state_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+    std::fs::rename(&tmp, &path)
+}
+
+/// Reads the state, changes it and writes it back, without another update slipping in between
+pub fn update_state<T>(change: impl FnOnce(&mut PersistedState) -> T) -> T {
+    let _guard = STATE_FILE_LOCK.lock().unwrap();
+
+    let mut state = read_state();
+    let result = change(&mut state);
+    if let Err(e) = write_state(&state) {
+        eprintln!("Couldn't save the operation state: {}", e);
+    }
+
+    result
+}
+
+pub fn load_interrupted() -> Vec<InterruptedOperation> {
+    let _guard = STATE_FILE_LOCK.lock().unwrap();
+    read_state().interrupted
+}
+
+pub fn record_started(info: &OperationInfo) {
+    update_state(|state| {
+        state.running.push(PersistedOperation {
+            id: info.id,
+            kind: info.kind,
+            install_dir: info.install_dir.clone(),
+            started_at: info.started_at.unwrap_or(info.queued_at),
+        })
+    });
+}
+
+/// Forgets a finished operation. A successful one also settles whatever was interrupted on the same install before.
+pub fn record_finished(info: &OperationInfo, completed: bool) {
+    let install_dir = normalize_install_dir(&info.install_dir);
+
+    update_state(|state| {
+        state.running.retain(|op| op.id != info.id);
+        if completed {
+            state
+                .interrupted
+                .retain(|op| normalize_install_dir(&op.install_dir) != install_dir);
+        }
+    });
+}
+
+/// Drops the interrupted operations of an install once the user chose to leave it as it is
+pub fn dismiss_interrupted(install_dir: &str) {
+    let install_dir = normalize_install_dir(install_dir);
+    update_state(|state| {
+        state
+            .interrupted
+            .retain(|op| normalize_install_dir(&op.install_dir) != install_dir)
+    });
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::config::install_state::{transition, InstallState};
+use crate::config::installed::get_object_by_location;
+use crate::manifest::downloader::get_cached_manifests;
+use crate::manifest::downloader::transaction::{discard_staged_files, has_pending_journal, rollback_journal, tmp_path};
+use crate::operations::operation_info::normalize_install_dir;
+use crate::operations::persist::{update_state, InterruptedOperation, PersistedOperation, RecoveryAction};
+use crate::operations::OperationKind;
+
+/// Temp files every cached build could have left in the install, which is everything a download stages
+async fn remove_temp_files(install_dir: &Path) -> usize {
+    let filenames: HashSet<String> = match get_cached_manifests().await {
+        Ok(manifests) => manifests
+            .into_iter()
+            .flat_map(|(_, manifest)| manifest.file_manifest_list.elements.into_iter().map(|fm| fm.filename))
+            .collect(),
+        Err(e) => {
+            eprintln!("Couldn't read the cached manifests, leaving temp files alone: {}", e);
+            return 0;
+        }
+    };
+
+    let leftover: Vec<&String> = filenames
+        .iter()
+        .filter(|filename| tmp_path(install_dir, filename).exists())
+        .collect();
+
+    discard_staged_files(install_dir, leftover.iter().copied()).await;
+    leftover.len()
+}
+
+async fn recover(op: PersistedOperation) -> InterruptedOperation {
+    let install_dir = Path::new(&op.install_dir);
+
+    // An update that crashed mid-commit is put back to the previous build, which the installed data still names
+    let mut rolled_back = false;
+    if has_pending_journal(install_dir) {
+        match rollback_journal(install_dir).await {
+            Ok(()) => rolled_back = true,
+            Err(e) => eprintln!("Rolling back the interrupted update in {:?} failed: {}", install_dir, e),
+        }
+    }
+
+    let removed_temp_files = match install_dir.is_dir() {
+        true => remove_temp_files(install_dir).await,
+        false => 0,
+    };
+
+    let action = match op.kind {
+        OperationKind::Install | OperationKind::Update | OperationKind::Uninstall => RecoveryAction::Resume,
+        OperationKind::Verify | OperationKind::Repair | OperationKind::Move => RecoveryAction::Repair,
+    };
+
+    // Only a registered install is marked, an interrupted import or move may have been working on another folder
+    let registered = get_object_by_location(&op.install_dir).await.is_ok();
+    if registered {
+        if let Err(e) = transition(&op.install_dir, InstallState::NeedsRepair, |_| {}).await {
+            eprintln!("Couldn't mark {} as needing repair: {}", op.install_dir, e);
+        }
+    }
+
+    InterruptedOperation {
+        kind: op.kind,
+        install_dir: op.install_dir,
+        started_at: op.started_at,
+        action,
+        rolled_back,
+        removed_temp_files,
+    }
+}
+
+/// Cleans up after every operation that was still running when the launcher last exited, and remembers them
+/// so the UI can offer to resume or repair. Must run before any new operation starts.
+pub async fn recover_interrupted_operations() -> Vec<InterruptedOperation> {
+    // Left in the running list until they're recovered, so a crash during recovery just means another go next time
+    let running = update_state(|state| state.running.clone());
+    let ids: Vec<u64> = running.iter().map(|op| op.id).collect();
+
+    let mut recovered = Vec::new();
+    for op in running {
+        println!("Recovering interrupted {:?} in {}", op.kind, op.install_dir);
+        recovered.push(recover(op).await);
+    }
+
+    update_state(|state| {
+        state.running.retain(|op| !ids.contains(&op.id));
+        for op in recovered {
+            // Only the latest interruption of an install matters
+            let install_dir = normalize_install_dir(&op.install_dir);
+            state
+                .interrupted
+                .retain(|other| normalize_install_dir(&other.install_dir) != install_dir);
+            state.interrupted.push(op);
+        }
+        state.interrupted.clone()
+    })
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy)]
+#[error("The download source appears to be down, please try again later")]
+pub struct CircuitOpen;
+
+struct BreakerState {
+    consecutive_failures: u32,
+    consecutive_trips: u32,
+    open_until: Option<Instant>,
+    outage_started: Option<Instant>,
+}
+
+/// Shared by every request to one source. Once enough requests fail in a row the breaker opens and everyone pauses,
+/// instead of each chunk burning through its retries against a source that is down.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cool_down: Duration,
+    max_cool_down: Duration,
+    give_up_after: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cool_down: Duration, max_cool_down: Duration, give_up_after: Duration) -> Self {
+        Self {
+            failure_threshold,
+            cool_down,
+            max_cool_down,
+            give_up_after,
+            state: Mutex::new(BreakerState {
+                consecutive_failures: 0,
+                consecutive_trips: 0,
+                open_until: None,
+                outage_started: None,
+            }),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.consecutive_trips = 0;
+        state.open_until = None;
+        state.outage_started = None;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+
+        if state.consecutive_failures < self.failure_threshold || state.open_until.is_some() {
+            return;
+        }
+
+        // Each trip in a row waits twice as long before letting requests through again
+        let cool_down = self
+            .cool_down
+            .saturating_mul(1 << state.consecutive_trips.min(16))
+            .min(self.max_cool_down);
+
+        eprintln!("Download source looks down, pausing requests for {:?}", cool_down);
+
+        state.consecutive_trips += 1;
+        state.consecutive_failures = 0;
+        state.open_until = Some(Instant::now() + cool_down);
+        state.outage_started.get_or_insert_with(Instant::now);
+    }
+
+    /// Waits out an open breaker. Fails once the source has been down for too long to keep waiting.
+    pub async fn wait_until_closed(&self) -> Result<(), CircuitOpen> {
+        let open_until = {
+            let mut state = self.state.lock().unwrap();
+
+            if let Some(outage_started) = state.outage_started {
+                if outage_started.elapsed() >= self.give_up_after {
+                    return Err(CircuitOpen);
+                }
+            }
+
+            match state.open_until {
+                Some(open_until) if open_until > Instant::now() => open_until,
+                _ => {
+                    // Half open: let requests through, the next failure streak trips it again
+                    state.open_until = None;
+                    return Ok(());
+                }
+            }
+        };
+
+        tokio::time::sleep_until(open_until.into()).await;
+        Ok(())
+    }
+
+    /// Forgets a previous outage so a new operation gets a fresh chance
+    pub fn reset(&self) {
+        self.record_success();
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(
+            8,
+            Duration::from_secs(5),
+            Duration::from_secs(60),
+            Duration::from_secs(5 * 60),
+        )
+    }
+}
+
+static CIRCUIT_BREAKERS: OnceLock<Mutex<HashMap<String, Arc<CircuitBreaker>>>> = OnceLock::new();
+
+/// Returns the breaker shared by every request to a bucket or URL
+pub fn circuit_breaker_for(source: &str) -> Arc<CircuitBreaker> {
+    CIRCUIT_BREAKERS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+        .entry(source.to_string())
+        .or_default()
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_open(breaker: &CircuitBreaker) -> bool {
+        breaker.state.lock().unwrap().open_until.is_some()
+    }
+
+    #[tokio::test]
+    async fn trips_after_enough_failures_in_a_row() {
+        let breaker = CircuitBreaker::new(3, Duration::from_millis(50), Duration::from_secs(1), Duration::from_secs(10));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(!is_open(&breaker));
+
+        breaker.record_failure();
+        assert!(is_open(&breaker));
+
+        let started = Instant::now();
+        breaker.wait_until_closed().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(40));
+
+        // Half open once the cool down is over
+        breaker.wait_until_closed().await.unwrap();
+        assert!(!is_open(&breaker));
+    }
+
+    #[test]
+    fn cool_down_doubles_with_each_trip_up_to_the_cap() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(10), Duration::from_secs(25), Duration::from_secs(600));
+        let remaining = |breaker: &CircuitBreaker| {
+            let mut state = breaker.state.lock().unwrap();
+            let remaining = state.open_until.take().unwrap() - Instant::now();
+            Duration::from_secs(remaining.as_secs_f64().round() as u64)
+        };
+
+        breaker.record_failure();
+        assert_eq!(remaining(&breaker), Duration::from_secs(10));
+        breaker.record_failure();
+        assert_eq!(remaining(&breaker), Duration::from_secs(20));
+        breaker.record_failure();
+        assert_eq!(remaining(&breaker), Duration::from_secs(25));
+    }
+
+    #[tokio::test]
+    async fn gives_up_once_the_source_has_been_down_too_long() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(10), Duration::from_millis(10), Duration::from_millis(30));
+
+        breaker.record_failure();
+        breaker.wait_until_closed().await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert!(breaker.wait_until_closed().await.is_err());
+
+        breaker.reset();
+        assert!(breaker.wait_until_closed().await.is_ok());
+    }
+}
@@ -0,0 +1,190 @@
+pub mod circuit_breaker;
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Display;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::time::sleep;
+
+pub use circuit_breaker::{circuit_breaker_for, CircuitBreaker, CircuitOpen};
+
+/// Whether an error is worth trying again or will fail the same way every time
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
+
+impl Retryable for anyhow::Error {
+    fn is_retryable(&self) -> bool {
+        true
+    }
+}
+
+/// How often and how patiently a failed request is retried
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Fraction of each backoff that is randomized so parallel tasks don't retry in lockstep
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Single chunk and object requests
+    pub const CHUNK: RetryPolicy = RetryPolicy {
+        max_retries: 3,
+        initial_backoff: Duration::from_millis(500),
+        max_backoff: Duration::from_secs(8),
+        jitter: 0.5,
+    };
+
+    /// Rebuilding a whole file after it failed verification
+    pub const FILE: RetryPolicy = RetryPolicy {
+        max_retries: 3,
+        initial_backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(10),
+        jitter: 0.5,
+    };
+
+    /// Uploading chunks from the CLI
+    pub const UPLOAD: RetryPolicy = RetryPolicy {
+        max_retries: 5,
+        initial_backoff: Duration::from_millis(250),
+        max_backoff: Duration::from_secs(30),
+        jitter: 0.5,
+    };
+
+    /// Delay before the given retry, starting at 1. Doubles each time up to the cap, minus a random share.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(16);
+        let base = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+
+        base.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * random_fraction())
+    }
+
+    /// Runs an operation, retrying retryable errors
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        label: &str,
+        breaker: Option<&CircuitBreaker>,
+        operation: F,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Retryable + From<CircuitOpen> + Display,
+    {
+        self.run_if(label, breaker, operation, E::is_retryable).await
+    }
+
+    /// Runs an operation, retrying the errors `should_retry` accepts. With a breaker, failures count towards
+    /// pausing every request to the same source.
+    pub async fn run_if<T, E, F, Fut, R>(
+        &self,
+        label: &str,
+        breaker: Option<&CircuitBreaker>,
+        mut operation: F,
+        should_retry: R,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: From<CircuitOpen> + Display,
+        R: Fn(&E) -> bool,
+    {
+        let mut retry = 0;
+
+        loop {
+            if let Some(breaker) = breaker {
+                breaker.wait_until_closed().await?;
+            }
+
+            match operation().await {
+                Ok(value) => {
+                    if let Some(breaker) = breaker {
+                        breaker.record_success();
+                    }
+                    return Ok(value);
+                }
+                Err(e) if should_retry(&e) => {
+                    if let Some(breaker) = breaker {
+                        breaker.record_failure();
+                    }
+
+                    retry += 1;
+                    if retry > self.max_retries {
+                        return Err(e);
+                    }
+
+                    let backoff = self.backoff(retry);
+                    eprintln!(
+                        "{} failed (attempt {}/{}): {}. Retrying in {:?}...",
+                        label,
+                        retry,
+                        self.max_retries + 1,
+                        e,
+                        backoff
+                    );
+                    sleep(backoff).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// A value in [0, 1). `RandomState` is seeded randomly per thread and bumped on every call, which is plenty for jitter.
+fn random_fraction() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(nanos);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            jitter: 0.0,
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_backoff() {
+        let policy = RetryPolicy::CHUNK;
+
+        for retry in 1..=8 {
+            let base = policy
+                .initial_backoff
+                .saturating_mul(1 << (retry - 1))
+                .min(policy.max_backoff);
+
+            for _ in 0..100 {
+                let backoff = policy.backoff(retry);
+                assert!(backoff <= base, "{:?} is over {:?}", backoff, base);
+                assert!(backoff >= base.mul_f64(1.0 - policy.jitter), "{:?} is under the jitter", backoff);
+            }
+        }
+    }
+}
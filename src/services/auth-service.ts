@@ -32,7 +32,7 @@ export const authService = {
             console.log("Sign in successful")
 
             if (rememberMe) {
-                await this.saveUserCredentials(account_info.RefreshToken)
+                await this.saveUserCredentials()
             }
 
             return account_info
@@ -45,11 +45,10 @@ export const authService = {
     /**
      * Save user credentials for remember me functionality
      */
-    async saveUserCredentials(refreshToken: string): Promise<void> {
+    async saveUserCredentials(): Promise<void> {
         try {
             await invoke("saveu", {
-                enableRememberMe: true,
-                refreshToken
+                enableRememberMe: true
             })
             console.log("User credentials saved")
         } catch (error) {